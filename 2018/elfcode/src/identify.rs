@@ -0,0 +1,358 @@
+use crate::{Instruction, Opcode, Registers, Word};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
+/// One execution of an instruction whose opcode is only known by a
+/// scrambled code.
+pub trait Observation<C> {
+    type Code: Copy + Ord + fmt::Debug;
+
+    fn code(&self) -> Self::Code;
+
+    /// Whether `candidate` could have caused what was observed.
+    fn allows(&self, candidate: &C) -> bool;
+}
+
+/// What `identify` could not work out.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unresolved<K, C> {
+    /// Codes that were pinned down nonetheless.
+    pub known: BTreeMap<K, C>,
+    /// Codes with more than one candidate left.
+    pub ambiguous: BTreeMap<K, Vec<C>>,
+    /// Codes whose samples disagree, and all codes that compete for fewer
+    /// candidates than there are of them, since any of them could be the
+    /// one that is wrong.
+    pub contradictions: BTreeSet<K>,
+}
+
+/// The registers around one instruction in a day 16 style listing:
+///
+/// ```text
+/// Before: [3, 2, 1, 1]
+/// 9 2 1 2
+/// After:  [3, 2, 2, 1]
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Sample<W, const N: usize> {
+    pub before: Registers<W, N>,
+    pub code: W,
+    pub a: W,
+    pub b: W,
+    pub c: W,
+    pub after: Registers<W, N>,
+}
+
+type Options<K, C> = BTreeMap<K, BTreeSet<C>>;
+
+/// Works out which candidate every code in `samples` stands for, assuming
+/// that no two codes stand for the same candidate. Candidates are narrowed
+/// down by the samples, then codes that cannot all get a candidate at once
+/// are set aside as contradictions. The rest are narrowed down further by
+/// elimination, and where that stalls, by ruling out pairs that do not fit
+/// in any complete assignment.
+pub fn identify<O, C>(
+    samples: &[O],
+    candidates: &[C],
+) -> Result<BTreeMap<O::Code, C>, Unresolved<O::Code, C>>
+where
+    O: Observation<C>,
+    C: Copy + Ord,
+{
+    let mut options = Options::new();
+    for sample in samples {
+        let allowed = options
+            .entry(sample.code())
+            .or_insert_with(|| candidates.iter().copied().collect());
+        allowed.retain(|c| sample.allows(c));
+    }
+
+    // The remaining codes can all be matched at once.
+    let contradictions = conflicts(&options);
+    options.retain(|code, _| !contradictions.contains(code));
+    loop {
+        eliminate(&mut options);
+        if options.values().all(|cs| cs.len() == 1) || !prune(&mut options) {
+            break;
+        }
+    }
+
+    let mut unresolved = Unresolved {
+        known: BTreeMap::new(),
+        ambiguous: BTreeMap::new(),
+        contradictions,
+    };
+    for (code, cs) in options {
+        let cs = cs.into_iter().collect::<Vec<_>>();
+        match cs[..] {
+            [c] => {
+                unresolved.known.insert(code, c);
+            },
+            _ => {
+                unresolved.ambiguous.insert(code, cs);
+            },
+        }
+    }
+
+    if unresolved.ambiguous.is_empty() && unresolved.contradictions.is_empty() {
+        Ok(unresolved.known)
+    } else {
+        Err(unresolved)
+    }
+}
+
+/// Takes candidates that are the only option for some code away from all
+/// other codes.
+fn eliminate<K: Copy + Ord, C: Copy + Ord>(options: &mut Options<K, C>) {
+    let mut done = BTreeSet::new();
+    loop {
+        let single = options.iter().find_map(|(&code, cs)| match cs.len() {
+            1 if !done.contains(&code) => Some((code, *cs.iter().next()?)),
+            _ => None,
+        });
+        let (code, c) = match single {
+            Some(single) => single,
+            None => return,
+        };
+        done.insert(code);
+        for (_, cs) in options.iter_mut().filter(|(&k, _)| k != code) {
+            cs.remove(&c);
+        }
+    }
+}
+
+/// Drops every pair that no complete assignment uses, returning whether
+/// anything was dropped. Nothing is dropped if there is no complete
+/// assignment at all.
+fn prune<K: Copy + Ord, C: Copy + Ord>(options: &mut Options<K, C>) -> bool {
+    if matching(options).len() < options.len() {
+        return false;
+    }
+    let mut pruned = false;
+    let codes = options.keys().copied().collect::<Vec<_>>();
+    for code in codes {
+        for c in options[&code].clone() {
+            let mut fixed = options.clone();
+            for (&k, cs) in &mut fixed {
+                if k == code {
+                    *cs = Some(c).into_iter().collect();
+                } else {
+                    cs.remove(&c);
+                }
+            }
+            if matching(&fixed).len() < fixed.len() {
+                options.get_mut(&code).unwrap().remove(&c);
+                pruned = true;
+            }
+        }
+    }
+    pruned
+}
+
+/// Codes that are left without a candidate by some maximum matching,
+/// found by following alternating paths from the ones `matching` leaves
+/// out.
+fn conflicts<K: Copy + Ord, C: Copy + Ord>(
+    options: &Options<K, C>,
+) -> BTreeSet<K> {
+    let owners = matching(options);
+    let matched = owners.values().copied().collect::<BTreeSet<_>>();
+    let mut todo = options
+        .keys()
+        .copied()
+        .filter(|code| !matched.contains(code))
+        .collect::<Vec<_>>();
+    let mut found = todo.iter().copied().collect::<BTreeSet<_>>();
+    while let Some(code) = todo.pop() {
+        for c in &options[&code] {
+            if let Some(&owner) = owners.get(c) {
+                if found.insert(owner) {
+                    todo.push(owner);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// A maximum matching of codes to candidates, as the code each matched
+/// candidate belongs to.
+fn matching<K: Copy + Ord, C: Copy + Ord>(
+    options: &Options<K, C>,
+) -> BTreeMap<C, K> {
+    fn augment<K: Copy + Ord, C: Copy + Ord>(
+        code: K,
+        options: &Options<K, C>,
+        seen: &mut BTreeSet<C>,
+        owners: &mut BTreeMap<C, K>,
+    ) -> bool {
+        for &c in &options[&code] {
+            if !seen.insert(c) {
+                continue;
+            }
+            let free = match owners.get(&c) {
+                Some(&owner) => augment(owner, options, seen, owners),
+                None => true,
+            };
+            if free {
+                owners.insert(c, code);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owners = BTreeMap::new();
+    for &code in options.keys() {
+        augment(code, options, &mut BTreeSet::new(), &mut owners);
+    }
+    owners
+}
+
+impl<W: Word, const N: usize> Observation<Opcode> for Sample<W, N> {
+    type Code = W;
+
+    fn code(&self) -> W {
+        self.code
+    }
+
+    fn allows(&self, op: &Opcode) -> bool {
+        let instr = Instruction::new(*op, self.a, self.b, self.c);
+        self.before.after(&instr) == Some(self.after)
+    }
+}
+
+impl<W: Word, const N: usize> FromStr for Sample<W, N>
+where
+    W::Err: Error + 'static,
+{
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s.trim().lines().map(str::trim).collect::<Vec<_>>();
+        let (before, instr, after) = match lines[..] {
+            [before, instr, after] => (before, instr, after),
+            _ => return aoc::err!("invalid sample: {:?}", s),
+        };
+        let registers = |line: &str, prefix| match line.strip_prefix(prefix) {
+            Some(regs) => regs.parse::<Registers<W, N>>(),
+            None => aoc::err!("expected {:?}, got {:?}", prefix, line),
+        };
+        let xs = instr
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<W>, _>>()?;
+        match xs[..] {
+            [code, a, b, c] => Ok(Sample {
+                before: registers(before, "Before:")?,
+                code,
+                a,
+                b,
+                c,
+                after: registers(after, "After:")?,
+            }),
+            _ => aoc::err!("invalid instruction: {:?}", instr),
+        }
+    }
+}
+
+impl<K: fmt::Debug, C: fmt::Debug> fmt::Display for Unresolved<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not identify all opcodes")?;
+        for (code, cs) in &self.ambiguous {
+            write!(f, "; {:?} could be any of {:?}", code, cs)?;
+        }
+        if !self.contradictions.is_empty() {
+            write!(f, "; no candidate fits {:?}", self.contradictions)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: fmt::Debug, C: fmt::Debug> Error for Unresolved<K, C> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A code and the candidates a sample of it allows.
+    #[derive(Clone)]
+    struct Allowed(u8, &'static str);
+
+    impl Observation<char> for Allowed {
+        type Code = u8;
+
+        fn code(&self) -> u8 {
+            self.0
+        }
+
+        fn allows(&self, c: &char) -> bool {
+            self.1.contains(*c)
+        }
+    }
+
+    #[test]
+    fn elimination_and_matching() {
+        let candidates = ['a', 'b', 'c', 'd'];
+        let samples = [
+            Allowed(0, "ab"),
+            Allowed(1, "ab"),
+            Allowed(2, "abc"),
+            Allowed(2, "abcd"),
+            Allowed(3, "abcd"),
+        ];
+        // No code is down to one candidate, but 0 and 1 need both a and b.
+        let unresolved = identify(&samples, &candidates).unwrap_err();
+        let known = [(2, 'c'), (3, 'd')].iter().copied().collect();
+        assert_eq!(
+            unresolved,
+            Unresolved {
+                known,
+                ambiguous: [(0, vec!['a', 'b']), (1, vec!['a', 'b'])]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                contradictions: BTreeSet::new(),
+            }
+        );
+
+        let mut samples = samples.to_vec();
+        samples.push(Allowed(1, "bc"));
+        let mapping = identify(&samples, &candidates).unwrap();
+        assert_eq!(mapping.values().collect::<String>(), "abcd");
+
+        // 0 and 1 both need b, so either of them could be wrong.
+        samples.push(Allowed(0, "b"));
+        samples.push(Allowed(3, "x"));
+        let unresolved = identify(&samples, &candidates).unwrap_err();
+        let contradictions = [0, 1, 3].iter().copied().collect();
+        assert_eq!(unresolved.contradictions, contradictions);
+        assert!(unresolved.known.is_empty());
+        assert_eq!(unresolved.ambiguous[&2], ['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn conflicting_codes() {
+        let candidates = ['a', 'b', 'c'];
+        let samples = [Allowed(0, "a"), Allowed(1, "a"), Allowed(2, "bc")];
+        let unresolved = identify(&samples, &candidates).unwrap_err();
+        assert_eq!(
+            unresolved,
+            Unresolved {
+                known: BTreeMap::new(),
+                ambiguous: [(2, vec!['b', 'c'])].iter().cloned().collect(),
+                contradictions: [0, 1].iter().copied().collect(),
+            }
+        );
+
+        // Which code comes first does not matter.
+        let samples = [Allowed(2, "a"), Allowed(1, "a"), Allowed(0, "bc")];
+        let unresolved = identify(&samples, &candidates).unwrap_err();
+        let contradictions = [1, 2].iter().copied().collect();
+        assert_eq!(unresolved.contradictions, contradictions);
+    }
+}
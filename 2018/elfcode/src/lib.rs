@@ -0,0 +1,166 @@
+//! The register machine from 2018 days 16, 19 and 21, generic over the
+//! word type and the number of registers.
+
+mod identify;
+mod program;
+mod registers;
+mod vm;
+mod word;
+
+pub use identify::{identify, Observation, Sample, Unresolved};
+pub use program::Program;
+pub use registers::Registers;
+pub use vm::VM;
+pub use word::Word;
+
+use std::{fmt, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Opcode {
+    Addr,
+    Addi,
+    Mulr,
+    Muli,
+    Banr,
+    Bani,
+    Borr,
+    Bori,
+    Setr,
+    Seti,
+    Gtir,
+    Gtri,
+    Gtrr,
+    Eqir,
+    Eqri,
+    Eqrr,
+}
+
+/// How an instruction uses its `a` or `b` operand.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Register,
+    Immediate,
+    Ignored,
+}
+
+/// An instruction with raw operands. `c` always names the register that
+/// receives the result.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Instruction<W> {
+    pub op: Opcode,
+    pub a: W,
+    pub b: W,
+    pub c: W,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 16] = [
+        Opcode::Addr,
+        Opcode::Addi,
+        Opcode::Mulr,
+        Opcode::Muli,
+        Opcode::Banr,
+        Opcode::Bani,
+        Opcode::Borr,
+        Opcode::Bori,
+        Opcode::Setr,
+        Opcode::Seti,
+        Opcode::Gtir,
+        Opcode::Gtri,
+        Opcode::Gtrr,
+        Opcode::Eqir,
+        Opcode::Eqri,
+        Opcode::Eqrr,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        use Opcode::*;
+        match self {
+            Addr => "addr",
+            Addi => "addi",
+            Mulr => "mulr",
+            Muli => "muli",
+            Banr => "banr",
+            Bani => "bani",
+            Borr => "borr",
+            Bori => "bori",
+            Setr => "setr",
+            Seti => "seti",
+            Gtir => "gtir",
+            Gtri => "gtri",
+            Gtrr => "gtrr",
+            Eqir => "eqir",
+            Eqri => "eqri",
+            Eqrr => "eqrr",
+        }
+    }
+
+    /// How the `a` and `b` operands are used.
+    pub fn operands(self) -> [Operand; 2] {
+        use Opcode::*;
+        use Operand::*;
+        match self {
+            Addr | Mulr | Banr | Borr | Gtrr | Eqrr => [Register, Register],
+            Addi | Muli | Bani | Bori | Gtri | Eqri => [Register, Immediate],
+            Gtir | Eqir => [Immediate, Register],
+            Setr => [Register, Ignored],
+            Seti => [Immediate, Ignored],
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Opcode::ALL.iter().find(|op| op.mnemonic() == s) {
+            Some(&op) => Ok(op),
+            None => aoc::err!("unknown opcode: {:?}", s),
+        }
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    pub fn new(op: Opcode, a: W, b: W, c: W) -> Self {
+        Instruction { op, a, b, c }
+    }
+
+    /// Whether every operand naming a register is below `registers`.
+    pub fn is_valid(&self, registers: usize) -> bool {
+        let [a, b] = self.op.operands();
+        let fits = |x: W| x.to_usize() < registers;
+        (a != Operand::Register || fits(self.a))
+            && (b != Operand::Register || fits(self.b))
+            && fits(self.c)
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.op, self.a, self.b, self.c)
+    }
+}
+
+impl<W: Word> FromStr for Instruction<W>
+where
+    W::Err: std::error::Error + 'static,
+{
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+        match parts[..] {
+            [op, a, b, c] => {
+                let (a, b, c) = (a.parse()?, b.parse()?, c.parse()?);
+                Ok(Instruction::new(op.parse()?, a, b, c))
+            },
+            _ => aoc::err!("invalid instruction: {:?}", s),
+        }
+    }
+}
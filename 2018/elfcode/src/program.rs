@@ -0,0 +1,56 @@
+use crate::{Instruction, Word};
+use std::{marker::PhantomData, str::FromStr};
+
+/// A list of instructions for a machine with `N` registers, optionally
+/// with one register bound to the instruction pointer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Program<W, const N: usize> {
+    ip: Option<usize>,
+    instructions: Vec<Instruction<W>>,
+    registers: PhantomData<[W; N]>,
+}
+
+impl<W: Word, const N: usize> Program<W, N> {
+    /// Fails if an instruction or `ip` names a register past the last one.
+    pub fn new(
+        ip: Option<usize>,
+        instructions: Vec<Instruction<W>>,
+    ) -> aoc::Result<Self> {
+        if let Some(ip) = ip.filter(|&ip| ip >= N) {
+            return aoc::err!("no register {} to bind ip to", ip);
+        }
+        if let Some(instr) = instructions.iter().find(|i| !i.is_valid(N)) {
+            return aoc::err!("register out of range: {}", instr);
+        }
+        Ok(Program { ip, instructions, registers: PhantomData })
+    }
+
+    /// The register bound to the instruction pointer.
+    pub fn ip(&self) -> Option<usize> {
+        self.ip
+    }
+
+    pub fn instructions(&self) -> &[Instruction<W>] {
+        &self.instructions
+    }
+}
+
+/// Parses one instruction per line, preceded by an optional `#ip N`.
+impl<W: Word, const N: usize> FromStr for Program<W, N>
+where
+    W::Err: std::error::Error + 'static,
+{
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ip = None;
+        let mut instructions = Vec::new();
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match line.strip_prefix("#ip ") {
+                Some(r) => ip = Some(r.trim().parse()?),
+                None => instructions.push(line.parse()?),
+            }
+        }
+        Program::new(ip, instructions)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Instruction, Opcode, Word};
+use std::{fmt, ops, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Registers<W, const N: usize>(pub [W; N]);
+
+impl<W: Word, const N: usize> Default for Registers<W, N> {
+    fn default() -> Self {
+        Registers([W::default(); N])
+    }
+}
+
+impl<W: Word, const N: usize> Registers<W, N> {
+    /// Panics if `instr` names a register past the last one, which
+    /// `Instruction::is_valid` checks for.
+    pub fn execute(&mut self, instr: &Instruction<W>) {
+        use Opcode::*;
+        let regs = &self.0;
+        let r = |x: W| regs[x.to_usize()];
+        let (a, b) = (instr.a, instr.b);
+        let result = match instr.op {
+            Addr => r(a) + r(b),
+            Addi => r(a) + b,
+            Mulr => r(a) * r(b),
+            Muli => r(a) * b,
+            Banr => r(a) & r(b),
+            Bani => r(a) & b,
+            Borr => r(a) | r(b),
+            Bori => r(a) | b,
+            Setr => r(a),
+            Seti => a,
+            Gtir => W::from(a > r(b)),
+            Gtri => W::from(r(a) > b),
+            Gtrr => W::from(r(a) > r(b)),
+            Eqir => W::from(a == r(b)),
+            Eqri => W::from(r(a) == b),
+            Eqrr => W::from(r(a) == r(b)),
+        };
+        self.0[instr.c.to_usize()] = result;
+    }
+
+    /// The registers after executing `instr`, or `None` if it names a
+    /// register that does not exist or its result does not fit in a word.
+    pub fn after(&self, instr: &Instruction<W>) -> Option<Self> {
+        if !instr.is_valid(N) {
+            return None;
+        }
+        let r = |x: W| self.0[x.to_usize()];
+        let (a, b) = (instr.a, instr.b);
+        let fits = match instr.op {
+            Opcode::Addr => r(a).checked_add(r(b)).is_some(),
+            Opcode::Addi => r(a).checked_add(b).is_some(),
+            Opcode::Mulr => r(a).checked_mul(r(b)).is_some(),
+            Opcode::Muli => r(a).checked_mul(b).is_some(),
+            _ => true,
+        };
+        if !fits {
+            return None;
+        }
+        let mut copy = *self;
+        copy.execute(instr);
+        Some(copy)
+    }
+}
+
+impl<W, const N: usize> ops::Index<usize> for Registers<W, N> {
+    type Output = W;
+
+    fn index(&self, r: usize) -> &W {
+        &self.0[r]
+    }
+}
+
+impl<W, const N: usize> ops::IndexMut<usize> for Registers<W, N> {
+    fn index_mut(&mut self, r: usize) -> &mut W {
+        &mut self.0[r]
+    }
+}
+
+/// Formats as `[3, 2, 1, 1]`, like the samples in day 16.
+impl<W: fmt::Display, const N: usize> fmt::Display for Registers<W, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, x) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", x)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl<W: Word, const N: usize> FromStr for Registers<W, N>
+where
+    W::Err: std::error::Error + 'static,
+{
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let xs = s
+            .trim_matches(|c: char| c.is_whitespace() || c == '[' || c == ']')
+            .split(',')
+            .map(|x| x.trim().parse())
+            .collect::<Result<Vec<W>, _>>()?;
+        if xs.len() != N {
+            return aoc::err!("expected {} registers, got {:?}", N, s);
+        }
+        let mut regs = Self::default();
+        regs.0.copy_from_slice(&xs);
+        Ok(regs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overflow() {
+        let before = Registers::<u16, 4>([65535, 1, 0, 0]);
+        let after = |op, a, b| before.after(&Instruction::new(op, a, b, 2));
+        assert_eq!(after(Opcode::Addr, 0, 1), None);
+        assert_eq!(after(Opcode::Addi, 0, 1), None);
+        assert_eq!(after(Opcode::Mulr, 0, 0), None);
+        assert_eq!(after(Opcode::Muli, 0, 2), None);
+        let or = Registers([65535, 1, 65535, 0]);
+        assert_eq!(after(Opcode::Borr, 0, 1), Some(or));
+        assert_eq!(after(Opcode::Addi, 0, 0), Some(or));
+    }
+}
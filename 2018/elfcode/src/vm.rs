@@ -0,0 +1,95 @@
+use crate::{Program, Registers, Word};
+
+#[derive(Clone, Debug)]
+pub struct VM<W, const N: usize> {
+    pub registers: Registers<W, N>,
+    pub ip: usize,
+    /// Number of instructions executed so far.
+    pub executed: u64,
+}
+
+impl<W: Word, const N: usize> Default for VM<W, N> {
+    fn default() -> Self {
+        VM::new()
+    }
+}
+
+impl<W: Word, const N: usize> VM<W, N> {
+    pub fn new() -> Self {
+        VM { registers: Registers::default(), ip: 0, executed: 0 }
+    }
+
+    /// Executes the instruction at `ip`. Returns `false` without doing
+    /// anything once `ip` points outside of the program.
+    pub fn step(&mut self, program: &Program<W, N>) -> bool {
+        let instr = match program.instructions().get(self.ip) {
+            Some(instr) => instr,
+            None => return false,
+        };
+        match program.ip() {
+            Some(r) => {
+                self.registers[r] = W::from_usize(self.ip);
+                self.registers.execute(instr);
+                self.ip = self.registers[r].to_usize() + 1;
+            },
+            None => {
+                self.registers.execute(instr);
+                self.ip += 1;
+            },
+        }
+        self.executed += 1;
+        true
+    }
+
+    /// Runs the program until `ip` leaves it.
+    pub fn run(&mut self, program: &Program<W, N>) {
+        while self.step(program) {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Instruction, Opcode};
+
+    #[test]
+    fn bound_ip() -> aoc::Result<()> {
+        let program = "
+            #ip 0
+            seti 5 0 1
+            seti 6 0 2
+            addi 0 1 0
+            addr 1 2 3
+            setr 1 0 0
+            seti 8 0 4
+            seti 9 0 5
+        ";
+        let program = program.parse::<Program<u32, 6>>()?;
+        let mut vm = VM::new();
+        vm.run(&program);
+        assert_eq!(vm.registers.0, [6, 5, 6, 0, 0, 9]);
+        assert_eq!((vm.ip, vm.executed), (7, 5));
+        assert!("#ip 6\nseti 1 2 3".parse::<Program<u32, 6>>().is_err());
+        assert!("addr 1 6 0".parse::<Program<u32, 6>>().is_err());
+        assert!("bogus 1 2 3".parse::<Program<u32, 6>>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sample() -> aoc::Result<()> {
+        let before = "[3, 2, 1, 1]".parse::<Registers<u16, 4>>()?;
+        let after = "[3, 2, 2, 1]".parse::<Registers<u16, 4>>()?;
+        let matching = Opcode::ALL
+            .iter()
+            .filter(|&&op| {
+                let instr = Instruction::new(op, 2, 1, 2);
+                before.after(&instr) == Some(after)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(matching, [&Opcode::Addi, &Opcode::Mulr, &Opcode::Seti]);
+        assert_eq!(before.to_string(), "[3, 2, 1, 1]");
+        let invalid = Instruction::new(Opcode::Addr, 4, 0, 0);
+        assert_eq!(before.after(&invalid), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    fmt,
+    ops::{Add, BitAnd, BitOr, Mul},
+    str::FromStr,
+};
+
+/// The contents of a register. Operands are words as well, even when they
+/// name a register.
+pub trait Word:
+    Copy
+    + fmt::Debug
+    + fmt::Display
+    + Default
+    + Ord
+    + FromStr
+    + From<bool>
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+{
+    fn from_usize(x: usize) -> Self;
+
+    fn to_usize(self) -> usize;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                #[inline]
+                fn from_usize(x: usize) -> Self {
+                    x as $t
+                }
+
+                #[inline]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+
+                #[inline]
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                #[inline]
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(u16, u32, u64, usize);
@@ -0,0 +1,24 @@
+/// Runs a program using only the add and multiply opcodes, returning the
+/// final state of memory.
+pub fn run_intcode(mut intcode: Vec<usize>) -> Vec<usize> {
+    let mut head = 0;
+    loop {
+        match intcode[head] {
+            1 | 2 => (),
+            99 => return intcode,
+            _ => panic!("Unknown opcode encountered: {}", intcode[head]),
+        }
+
+        let a = intcode[intcode[head + 1]];
+        let b = intcode[intcode[head + 2]];
+        let c = intcode[head + 3];
+
+        intcode[c] = match intcode[head] {
+            1 => a + b,
+            2 => a * b,
+            _ => unreachable!(),
+        };
+
+        head += 4;
+    }
+}
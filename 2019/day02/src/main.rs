@@ -1,5 +1,7 @@
 use std::io::{self, Read, Write};
 
+use day02::run_intcode;
+
 mod aoc {
     pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 }
@@ -53,29 +55,6 @@ fn level2(s: &str) -> usize {
     panic!("No noun-verb combo found that produces the right value");
 }
 
-fn run_intcode(mut intcode: Vec<usize>) -> Vec<usize> {
-    let mut head = 0;
-    loop {
-        match intcode[head] {
-            1 | 2 => (),
-            99 => return intcode,
-            _ => panic!("Unknown opcode encountered: {}", intcode[head]),
-        }
-
-        let a = intcode[intcode[head + 1]];
-        let b = intcode[intcode[head + 2]];
-        let c = intcode[head + 3];
-
-        intcode[c] = match intcode[head] {
-            1 => a + b,
-            2 => a * b,
-            _ => unreachable!(),
-        };
-
-        head += 4;
-    }
-}
-
 fn main() -> aoc::Result<()> {
     env_logger::init();
     if let Err(e) = solve() {
@@ -0,0 +1,100 @@
+/// Runs a program with the given inputs, returning the last value it
+/// outputs.
+pub fn run_intcode(intcode: &mut [i32], inputs: &[i32]) -> i32 {
+    execute(intcode, inputs).last().copied().unwrap_or(0)
+}
+
+/// Runs a program to completion, returning everything it outputs.
+#[allow(clippy::needless_range_loop)]
+pub fn execute(intcode: &mut [i32], inputs: &[i32]) -> Vec<i32> {
+    let mut ip = 0;
+    let mut head = 0;
+    let mut outputs = Vec::new();
+    loop {
+        let mut instruction = intcode[ip];
+        let opcode = (instruction % 100) as u8;
+        instruction /= 100;
+        let mut mode = [0u8; 3];
+        for i in 0..3 {
+            mode[i] = (instruction % 10) as u8;
+            instruction /= 10;
+        }
+
+        match opcode {
+            1 | 2 | 7 | 8 => {
+                let mut args = [0; 2];
+                for i in 0..2 {
+                    let val = intcode[ip + i + 1];
+                    args[i] = match mode[i] {
+                        0 => intcode[val as usize],
+                        1 => val,
+                        _ => panic!("Unkown mode encountered: {}", mode[i]),
+                    };
+                }
+
+                let out = intcode[ip + 3] as usize;
+                intcode[out] = match opcode {
+                    1 => args[0] + args[1],
+                    2 => args[0] * args[1],
+                    7 =>
+                        if args[0] < args[1] {
+                            1
+                        } else {
+                            0
+                        },
+                    8 =>
+                        if args[0] == args[1] {
+                            1
+                        } else {
+                            0
+                        },
+                    _ => unreachable!(),
+                };
+
+                ip += 4;
+            },
+            3 => {
+                let address = intcode[ip + 1] as usize;
+                intcode[address] = inputs[head];
+                head += 1;
+                ip += 2;
+            },
+            4 => {
+                let val = intcode[ip + 1];
+                let out = match mode[0] {
+                    0 => intcode[val as usize],
+                    1 => val,
+                    _ => panic!("Unkown mode encountered: {}", mode[0]),
+                };
+                eprintln!("Output {}", out);
+                outputs.push(out);
+                ip += 2;
+            },
+            5 | 6 => {
+                let mut args = [0; 2];
+                for i in 0..2 {
+                    let val = intcode[ip + i + 1];
+                    args[i] = match mode[i] {
+                        0 => intcode[val as usize],
+                        1 => val,
+                        _ => panic!("Unkown mode encountered: {}", mode[i]),
+                    };
+                }
+
+                let b = match opcode {
+                    5 => args[0] != 0,
+                    6 => args[0] == 0,
+                    _ => unreachable!(),
+                };
+
+                if b {
+                    ip = args[1] as usize
+                } else {
+                    ip += 3;
+                }
+            },
+            99 => return outputs,
+            _ => panic!("Unknown opcode encountered: {}", opcode),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use std::io::{self, Read, Write};
 
+use day05::run_intcode;
+
 fn main() {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input).unwrap();
@@ -34,95 +36,6 @@ fn level2(intcode: &[i32]) -> i32 {
     run_intcode(&mut intcode, &[5])
 }
 
-fn run_intcode(intcode: &mut [i32], inputs: &[i32]) -> i32 {
-    let mut ip = 0;
-    let mut head = 0;
-    let mut output = 0;
-    loop {
-        let mut instruction = intcode[ip];
-        let opcode = (instruction % 100) as u8;
-        instruction /= 100;
-        let mut mode = [0u8; 3];
-        for i in 0..3 {
-            mode[i] = (instruction % 10) as u8;
-            instruction /= 10;
-        }
-
-        match opcode {
-            1 | 2 | 7 | 8 => {
-                let mut args = [0; 2];
-                for i in 0..2 {
-                    let val = intcode[ip + i + 1];
-                    args[i] = match mode[i] {
-                        0 => intcode[val as usize],
-                        1 => val,
-                        _ => panic!("Unkown mode encountered: {}", mode[i]),
-                    };
-                }
-
-                let out = intcode[ip + 3] as usize;
-                intcode[out] = match opcode {
-                    1 => args[0] + args[1],
-                    2 => args[0] * args[1],
-                    7 =>
-                        if args[0] < args[1] {
-                            1
-                        } else {
-                            0
-                        },
-                    8 =>
-                        if args[0] == args[1] {
-                            1
-                        } else {
-                            0
-                        },
-                    _ => unreachable!(),
-                };
-
-                ip += 4;
-            },
-            3 => {
-                let address = intcode[ip + 1] as usize;
-                intcode[address] = inputs[head];
-                head += 1;
-                ip += 2;
-            },
-            4 => {
-                let address = intcode[ip + 1] as usize;
-                let out = intcode[address];
-                eprintln!("Output {}", out);
-                output = out;
-                ip += 2;
-            },
-            5 | 6 => {
-                let mut args = [0; 2];
-                for i in 0..2 {
-                    let val = intcode[ip + i + 1];
-                    args[i] = match mode[i] {
-                        0 => intcode[val as usize],
-                        1 => val,
-                        _ => panic!("Unkown mode encountered: {}", mode[i]),
-                    };
-                }
-
-                let b = match opcode {
-                    5 => args[0] != 0,
-                    6 => args[0] == 0,
-                    _ => unreachable!(),
-                };
-
-                if b {
-                    ip = args[1] as usize
-                } else {
-                    ip += 3;
-                }
-            },
-            99 => return output,
-            _ => panic!("Unknown opcode encountered: {}", opcode),
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -155,6 +68,21 @@ mod test {
         assert_eq!(run_intcode(&mut intcode, &[8]), 0);
     }
 
+    #[test]
+    fn immediate_output() {
+        assert_eq!(run_intcode(&mut parse("104,42,99"), &[]), 42);
+
+        // Outputs 999 through `104` if the input is below 8.
+        let larger = parse(
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,\
+             98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,\
+             1000,1,20,4,20,1105,1,46,98,99",
+        );
+        for &(input, output) in &[(7, 999), (8, 1000), (9, 1001)] {
+            assert_eq!(run_intcode(&mut larger.clone(), &[input]), output);
+        }
+    }
+
     #[test]
     fn level1_sanity() {
         let parsed = parse(INPUT);
@@ -1,11 +1,10 @@
-use intcode::Signal;
+use intcode::{Event, Pool, ProgramImage};
 use std::{
     convert::TryFrom,
     io::{self, Read, Write},
-    sync::mpsc,
 };
 
-fn level1(original_vm: &intcode::VM) -> aoc::Result<u32> {
+fn level1(pool: &Pool) -> aoc::Result<u32> {
     let mut phase_settings = [0, 1, 2, 3, 4];
     let heap = permutohedron::Heap::new(&mut phase_settings);
     let mut thruster_signal = 0;
@@ -13,14 +12,14 @@ fn level1(original_vm: &intcode::VM) -> aoc::Result<u32> {
     for permutation in heap {
         let mut amplified_input = 0;
         for &phase in &permutation {
-            let mut vm = intcode::VM::with_mem(original_vm.mem());
-            let (input, output) = vm.setup_io();
-            input.send(Signal::Value(phase)).unwrap();
-            input.send(Signal::Value(amplified_input)).unwrap();
-            vm.run()?;
-            amplified_input = match output.recv().unwrap() {
-                Signal::Value(x) => x,
-                Signal::Halting =>
+            let mut vm = pool.get();
+            vm.provide_input(phase);
+            vm.provide_input(amplified_input);
+            amplified_input = match vm.resume()? {
+                Event::Output(x) => x,
+                Event::NeedsInput =>
+                    return aoc::err!("Amplifier requested too much input"),
+                Event::Halted =>
                     return aoc::err!("Amplifier halted before giving output"),
             };
         }
@@ -32,32 +31,30 @@ fn level1(original_vm: &intcode::VM) -> aoc::Result<u32> {
     Ok(thruster_signal)
 }
 
-fn level2(original_vm: &intcode::VM) -> aoc::Result<u32> {
+fn level2(pool: &Pool) -> aoc::Result<u32> {
     let mut phase_settings = [5, 6, 7, 8, 9];
     let mut thruster_signal = 0;
     let heap = permutohedron::Heap::new(&mut phase_settings);
     for permutation in heap {
-        let (init_tx, init_rx) = mpsc::channel();
-        let mut tx = init_tx.clone();
-        let mut rx = init_rx;
-        for &phase in &permutation {
-            let mut vm = intcode::VM::with_mem(original_vm.mem());
-            let (new_tx, new_rx) = mpsc::channel();
-            let cloned_tx = new_tx.clone();
-            vm.connect_io(rx, cloned_tx)?;
-            rayon::spawn(move || vm.run().unwrap());
-            tx.send(Signal::Value(phase)).unwrap();
-            tx = new_tx;
-            rx = new_rx;
-        }
+        let mut amplifiers = permutation
+            .iter()
+            .map(|&phase| {
+                let mut vm = pool.get();
+                vm.provide_input(phase);
+                vm
+            })
+            .collect::<Vec<_>>();
 
-        init_tx.send(Signal::Value(0)).unwrap();
         let mut amplified_input = 0;
-        for sig in rx.iter() {
-            let _ = init_tx.send(sig);
-            match sig {
-                Signal::Value(x) => amplified_input = x,
-                Signal::Halting => break,
+        'feedback: loop {
+            for vm in &mut amplifiers {
+                vm.provide_input(amplified_input);
+                amplified_input = match vm.resume()? {
+                    Event::Output(x) => x,
+                    Event::Halted => break 'feedback,
+                    Event::NeedsInput =>
+                        return aoc::err!("Amplifier stalled waiting for input"),
+                };
             }
         }
 
@@ -65,19 +62,18 @@ fn level2(original_vm: &intcode::VM) -> aoc::Result<u32> {
         thruster_signal = u32::max(result, thruster_signal);
     }
 
-    log::info!("{}", rayon::current_num_threads());
     Ok(thruster_signal)
 }
 
 fn solve() -> aoc::Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
-    let vm = intcode::VM::with_program(&input)?;
+    let pool = Pool::new(ProgramImage::parse(&input)?);
 
-    let some = level1(&vm)?;
+    let some = level1(&pool)?;
     writeln!(io::stderr(), "level 1: {}", some)?;
 
-    let thing = level2(&vm)?;
+    let thing = level2(&pool)?;
     writeln!(io::stderr(), "level 2: {}", thing)?;
 
     // stdout is used to submit solutions
@@ -108,11 +104,11 @@ mod test {
 
     #[test_log::new]
     fn sanity() -> aoc::Result<()> {
-        let vm = intcode::VM::with_program(INPUT)?;
-        let result = level1(&vm)?;
+        let pool = Pool::new(ProgramImage::parse(INPUT)?);
+        let result = level1(&pool)?;
         assert_eq!(result, 18812);
 
-        let result = level2(&vm)?;
+        let result = level2(&pool)?;
         assert_eq!(result, 25534964);
         Ok(())
     }
@@ -1,22 +1,19 @@
-use intcode::{self, Signal};
-use std::io::{self, Read, Write};
+use std::{
+    io::{self, Read, Write},
+    iter,
+};
 
 fn run_with_input(
-    ic: intcode::VM,
+    mut vm: intcode::VM,
     input_value: intcode::Value,
 ) -> aoc::Result<intcode::Value> {
-    let (input, output) = ic.spawn();
-    input.send(Signal::Value(input_value))?;
-    let mut result = None;
-    for o in output {
-        match o {
-            Signal::Value(x) => result = Some(x),
-            Signal::Halting if result == None =>
-                return aoc::err!("BOOST halted before giving output"),
-            Signal::Halting => break,
-        }
+    let mut output = Vec::new();
+    let input = intcode::io::Iter(iter::once(input_value));
+    vm.run_with(input, &mut output).into_result()?;
+    match output.last() {
+        Some(&x) => Ok(x),
+        None => aoc::err!("BOOST halted before giving output"),
     }
-    Ok(result.unwrap())
 }
 
 fn level1(program: &str) -> aoc::Result<intcode::Value> {
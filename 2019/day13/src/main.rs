@@ -1,8 +1,10 @@
-use intcode::Signal;
+use intcode::{Event, Signal, Transcript};
 use std::{
     collections::BTreeMap,
     convert::TryFrom,
+    env,
     fmt::{self, Display},
+    fs,
     io::{self, Read, Write},
 };
 
@@ -102,69 +104,61 @@ fn level1(orig_vm: &intcode::VM) -> aoc::Result<usize> {
     Ok(blocks)
 }
 
-fn level2(orig_vm: &intcode::VM) -> aoc::Result<i64> {
+/// Plays the game by keeping the paddle under the ball. The session is
+/// recorded so that it can be replayed without this driver.
+fn level2(orig_vm: &intcode::VM) -> aoc::Result<(i64, Transcript)> {
     use termion::{clear, cursor};
 
     let mut vm = intcode::VM::with_mem(orig_vm.mem());
     vm.mem_mut()[0] = 2;
-    let (tx, rx) = vm.spawn();
+    vm.enable_recording();
 
     let mut screen = Screen([Tile::Empty; SCREEN_WIDTH * SCREEN_HEIGHT]);
-    let mut ball_x = 21;
-    let mut updated = false;
+    let mut triple = Vec::with_capacity(3);
+    let mut ball_x: intcode::Value = 0;
+    let mut paddle_x = 0;
     let mut score = 0;
-    let mut paddle_xs = 21;
 
     eprint!("{}", clear::All);
     loop {
-        let x = match rx.recv()? {
-            Signal::Value(x) => x,
-            Signal::Halting => break,
-        };
-
-        let y = match rx.recv()? {
-            Signal::Value(y) => usize::try_from(y).unwrap(),
-            Signal::Halting => return aoc::err!("Unexpected halt"),
-        };
-
-        let value = match rx.recv()? {
-            Signal::Value(t) => t,
-            Signal::Halting => return aoc::err!("Unexpected halt"),
-        };
+        match vm.resume()? {
+            Event::Output(v) => triple.push(v),
+            Event::NeedsInput => {
+                vm.provide_input((ball_x - paddle_x).signum());
+                continue;
+            },
+            Event::Halted => break,
+        }
+        if triple.len() < 3 {
+            continue;
+        }
 
+        let (x, y, value) = (triple[0], triple[1], triple[2]);
+        triple.clear();
         if x >= 0 {
-            let x = usize::try_from(x)?;
+            let y = usize::try_from(y)?;
             let tile = Tile::try_from(value)?;
-            screen.0[y * SCREEN_HEIGHT + x] = tile;
+            screen.0[y * SCREEN_HEIGHT + usize::try_from(x)?] = tile;
             log::debug!("{:?}={:?}", (x, y), tile);
             eprint!("{}{}", cursor::Goto(x as u16 + 1, y as u16 + 1), tile);
 
-            if tile == Tile::Ball && x != ball_x {
-                ball_x = x;
-                let val = if paddle_xs < ball_x {
-                    1
-                } else if paddle_xs > ball_x {
-                    -1
-                } else {
-                    0
-                };
-                let _ = tx.send(Signal::Value(val));
-                updated = true;
-            } else if tile == Tile::Paddle && updated {
-                // std::thread::sleep_ms(25);
-                paddle_xs = x;
+            match tile {
+                Tile::Ball => ball_x = x,
+                Tile::Paddle => paddle_x = x,
+                _ => {},
             }
         } else {
             score = value;
             eprint!("{}Score: {}", cursor::Goto(45, 5), score);
             log::debug!("Score: {}", value);
         }
-
-        // eprint!("{}", screen);
     }
 
+    if !triple.is_empty() {
+        return aoc::err!("Unexpected halt");
+    }
     eprintln!("{}", clear::All);
-    Ok(score)
+    Ok((score, vm.take_transcript().unwrap_or_default()))
 }
 
 fn solve() -> aoc::Result<()> {
@@ -175,8 +169,11 @@ fn solve() -> aoc::Result<()> {
     let some = level1(&vm)?;
     writeln!(io::stderr(), "level 1: {}", some)?;
 
-    let thing = level2(&vm)?;
+    let (thing, transcript) = level2(&vm)?;
     writeln!(io::stderr(), "level 2: {}", thing)?;
+    if let Some(path) = env::args().nth(1) {
+        fs::write(path, transcript.to_string())?;
+    }
 
     // stdout is used to submit solutions
     writeln!(io::stdout(), "{}", thing)?;
@@ -198,3 +195,21 @@ fn main() -> aoc::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    const INPUT: &str = include_str!("../input.txt");
+
+    #[test_log::new]
+    fn level2_replay() -> aoc::Result<()> {
+        let vm = intcode::VM::with_program(INPUT)?;
+        let (score, transcript) = level2(&vm)?;
+        assert_eq!(transcript.outputs().last(), Some(&score));
+
+        let transcript = transcript.to_string().parse::<Transcript>()?;
+        let mut vm = intcode::VM::with_mem(vm.mem());
+        vm.mem_mut()[0] = 2;
+        transcript.replay(&mut vm)
+    }
+}
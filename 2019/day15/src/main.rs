@@ -1,9 +1,8 @@
-use intcode::Signal;
+use intcode::Event;
 use std::{
     collections::{HashMap as Map, HashSet as Set},
     convert::TryFrom,
     io::{self, Read, Write},
-    sync::mpsc,
 };
 
 const CARDINAL: [Direction; 4] =
@@ -73,18 +72,6 @@ impl Position {
     }
 }
 
-impl Direction {
-    fn reverse(self) -> Self {
-        use Direction::*;
-        match self {
-            North => South,
-            South => North,
-            East => West,
-            West => East,
-        }
-    }
-}
-
 fn level1(map: &Map<Position, Terrain>) -> aoc::Result<u32> {
     bfs(map, ORIGIN, |mapstate, pos| mapstate[&pos] == Terrain::Oxygen, |_| ())
         .ok_or_else(|| aoc::format_err!("Failed to find oxygen on the map"))
@@ -151,56 +138,35 @@ where
 }
 
 fn explore_area(vm: intcode::VM) -> aoc::Result<Map<Position, Terrain>> {
-    fn dfs(
-        map: &mut Map<Position, Terrain>,
-        current_pos: Position,
-        tx: &mpsc::Sender<Signal>,
-        rx: &mpsc::Receiver<Signal>,
-    ) -> aoc::Result<()> {
-        use Terrain::*;
-
-        let terrain = map[&current_pos];
-        match terrain {
-            Wall => Ok(()),
-            Empty | Oxygen => {
-                for &d in CARDINAL.iter() {
-                    let next_pos = current_pos.apply(d);
-                    if map.contains_key(&next_pos) {
-                        continue;
-                    }
-                    tx.send(Signal::Value(d.into())).unwrap();
-                    let resp = rx.recv().unwrap();
-                    let terrain = extract_status_code(resp)?;
-                    map.insert(next_pos, terrain);
-
-                    if terrain != Wall {
-                        dfs(map, next_pos, tx, rx)?;
-                        tx.send(Signal::Value(d.reverse().into())).unwrap();
-                        let resp = rx.recv().unwrap();
-                        let terrain = extract_status_code(resp)?;
-                        assert_ne!(terrain, Wall);
-                    }
-                }
-
-                Ok(())
-            },
+    let mut map = Map::new();
+    map.insert(ORIGIN, Terrain::Empty);
+    let mut frontier = vec![(ORIGIN, vm)];
+    while let Some((pos, droid)) = frontier.pop() {
+        for &d in CARDINAL.iter() {
+            let next_pos = pos.apply(d);
+            if map.contains_key(&next_pos) {
+                continue;
+            }
+
+            let mut fork = droid.clone();
+            fork.provide_input(d.into());
+            let terrain = extract_status_code(fork.resume()?)?;
+            map.insert(next_pos, terrain);
+            if terrain != Terrain::Wall {
+                frontier.push((next_pos, fork));
+            }
         }
     }
 
-    let (tx, rx) = vm.spawn();
-    let mut map = Map::new();
-    map.insert(ORIGIN, Terrain::Empty);
-    dfs(&mut map, ORIGIN, &tx, &rx)?;
-    let _ = tx.send(Signal::Halting);
     Ok(map)
 }
 
-fn extract_status_code(sig: Signal) -> aoc::Result<Terrain> {
-    let v = match sig {
-        Signal::Value(v) => v,
-        Signal::Halting => panic!("unexpected halt"),
-    };
-    Terrain::try_from(v)
+fn extract_status_code(event: Event) -> aoc::Result<Terrain> {
+    match event {
+        Event::Output(v) => Terrain::try_from(v),
+        Event::NeedsInput | Event::Halted =>
+            aoc::err!("Droid stopped before reporting status: {:?}", event),
+    }
 }
 
 fn solve() -> aoc::Result<()> {
@@ -1,4 +1,4 @@
-use intcode::Signal;
+use intcode::{ascii::Terminal, Signal};
 use num::Integer;
 use std::{
     collections::HashSet as Set,
@@ -195,29 +195,20 @@ fn level2(view: &View, mut vm: intcode::VM) -> aoc::Result<intcode::Value> {
     let main = path.replace(a, "A").replace(b, "B").replace(c, "C");
     vm.mem_mut()[0] = 2;
 
-    let (tx, rx) = vm.spawn();
-    for part in &[&main, a, b, c] {
+    let mut terminal = Terminal::new(vm);
+    for part in &[&main, a, b, c, "n"] {
         eprintln!("{}", part);
-        for c in part.bytes() {
-            eprint!("{} ", c);
-            let sig = Signal::Value(c as i64);
-            tx.send(sig).unwrap();
-        }
-        tx.send(Signal::Value(10)).unwrap();
-        eprintln!("10");
-    }
-    tx.send(Signal::Value(i64::from(b'n'))).unwrap();
-    tx.send(Signal::Value(10)).unwrap();
-
-    for msg in rx.recv() {
-        match msg {
-            Signal::Value(v) => eprint!("{}", u8::try_from(v).unwrap() as char),
-            Signal::Halting =>
-                return aoc::err!("Program halted before giving output"),
-        };
+        terminal.send_line(part)?;
     }
 
-    Ok(0)
+    let prompt = terminal.read_until_prompt()?;
+    eprint!("{}", prompt.text());
+    if !prompt.halted {
+        return aoc::err!("Program is waiting for more input");
+    }
+    prompt.values().last().ok_or_else(|| {
+        aoc::format_err!("Program halted without reporting collected dust")
+    })
 }
 
 fn is_scaffold(b: u8) -> bool {
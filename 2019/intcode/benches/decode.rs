@@ -0,0 +1,100 @@
+//! Compares running without the decode cache, with the cache filled as the
+//! program runs, precompiled, and on a `Pool` of precompiled machines. Every
+//! variant is also compared with the interpreter the VM replaced.
+//!
+//! On the amplifiers, nearly all of the gain over the old interpreter comes
+//! from no longer needing a thread per amplifier. The cache and the pool only
+//! add a little there, since each amplifier runs just a few instructions
+//! between inputs.
+//!
+//! Run with `cargo bench -p intcode --bench decode`.
+
+mod baseline;
+mod workloads;
+
+use intcode::{Pool, ProgramImage, Value, VM};
+use workloads::*;
+
+struct Workload {
+    name: &'static str,
+    rounds: u32,
+    program: &'static str,
+    vm: fn(&VM) -> Value,
+    /// Only for workloads that run the program many times.
+    pool: Option<fn(&Pool) -> Value>,
+    baseline: fn(&[Value]) -> Value,
+}
+
+fn bench(w: &Workload) {
+    let mut uncached = VM::with_program(w.program).unwrap();
+    uncached.set_decode_cache(false);
+    let lazy = VM::with_program(w.program).unwrap();
+    let mut precompiled = lazy.clone();
+    precompiled.precompile();
+    let pool = Pool::new(ProgramImage::new(lazy.mem()));
+
+    let expected = (w.baseline)(lazy.mem());
+    let old = time(w.rounds, || (w.baseline)(lazy.mem()));
+    let mut times = vec![("baseline", old)];
+    for (variant, vm) in [
+        ("uncached", &uncached),
+        ("lazy", &lazy),
+        ("precompiled", &precompiled),
+    ] {
+        assert_eq!((w.vm)(vm), expected, "{} {} disagrees", w.name, variant);
+        times.push((variant, time(w.rounds, || (w.vm)(vm))));
+    }
+    if let Some(f) = w.pool {
+        assert_eq!(f(&pool), expected, "{} pool disagrees", w.name);
+        times.push(("pool", time(w.rounds, || f(&pool))));
+    }
+
+    println!(
+        "{:<16}{:<12}{:>12}{:>14}{:>14}",
+        w.name, "", "time", "vs baseline", "vs uncached"
+    );
+    let uncached = times[1].1;
+    for (variant, secs) in times {
+        println!(
+            "{:<16}{:<12}{:>9.1} µs{:>13.2}x{:>13.2}x",
+            "",
+            variant,
+            secs * 1e6,
+            old / secs,
+            uncached / secs
+        );
+    }
+    println!();
+}
+
+fn main() {
+    let workloads = [
+        Workload {
+            name: "gravity assist",
+            rounds: 10,
+            program: GRAVITY_ASSIST,
+            vm: gravity_assist,
+            pool: Some(pool_gravity_assist),
+            baseline: baseline_gravity_assist,
+        },
+        Workload {
+            name: "amplifiers",
+            rounds: 50,
+            program: AMPLIFIERS,
+            vm: amplifiers,
+            pool: Some(pool_amplifiers),
+            baseline: baseline_amplifiers,
+        },
+        Workload {
+            name: "boost",
+            rounds: 20,
+            program: BOOST,
+            vm: boost,
+            pool: None,
+            baseline: baseline_boost,
+        },
+    ];
+    for w in &workloads {
+        bench(w);
+    }
+}
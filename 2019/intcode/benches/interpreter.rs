@@ -0,0 +1,40 @@
+//! Compares the VM with the interpreter it replaced, on a fresh machine per
+//! run like the puzzle solutions use it. The decode cache is turned off, so
+//! that only the interpreters themselves are compared; `decode` measures
+//! what the cache adds.
+//!
+//! Run with `cargo bench -p intcode --bench interpreter`.
+
+mod baseline;
+mod workloads;
+
+use intcode::{Value, VM};
+use workloads::*;
+
+fn bench(
+    name: &str,
+    rounds: u32,
+    f: fn(&VM) -> Value,
+    baseline: fn(&[Value]) -> Value,
+    program: &str,
+) {
+    let mut vm = VM::with_program(program).unwrap();
+    vm.set_decode_cache(false);
+    assert_eq!(f(&vm), baseline(vm.mem()), "{} disagrees", name);
+    let old = time(rounds, || baseline(vm.mem()));
+    let new = time(rounds, || f(&vm));
+    println!("{:<16}{:<12}{:>12.1} µs", name, "baseline", old * 1e6);
+    println!("{:<16}{:<12}{:>12.1} µs", name, "vm", new * 1e6);
+    println!("{:<16}{:<12}{:>12.2}x\n", name, "speedup", old / new);
+}
+
+fn main() {
+    bench(
+        "gravity assist",
+        10,
+        gravity_assist,
+        baseline_gravity_assist,
+        GRAVITY_ASSIST,
+    );
+    bench("boost", 20, boost, baseline_boost, BOOST);
+}
@@ -0,0 +1,182 @@
+//! Puzzle solutions the benchmarks run, on the VM, on a `Pool` and on the
+//! interpreter the VM replaced.
+
+#![allow(dead_code)]
+
+use crate::baseline;
+use intcode::{io, Event, Pool, Value, VM};
+use std::{hint::black_box, iter, sync::mpsc, thread, time::Instant};
+
+pub const GRAVITY_ASSIST: &str = include_str!("../../../day02/input.txt");
+pub const AMPLIFIERS: &str = include_str!("../../../day07/input.txt");
+pub const BOOST: &str = include_str!("../../../day09/input.txt");
+
+/// Average time `f` takes over `rounds` runs, after one to warm up.
+pub fn time(rounds: u32, mut f: impl FnMut() -> Value) -> f64 {
+    black_box(f());
+    let start = Instant::now();
+    for _ in 0..rounds {
+        black_box(f());
+    }
+    start.elapsed().as_secs_f64() / f64::from(rounds)
+}
+
+/// Day 2 part 2: tries every noun and verb on a fresh copy of the program.
+/// The writes hit the parameters of the very first instruction.
+pub fn gravity_assist(template: &VM) -> Value {
+    let mut found = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            if gravity_assist_once(&mut template.clone(), noun, verb) {
+                found = 100 * noun + verb;
+            }
+        }
+    }
+    found
+}
+
+pub fn pool_gravity_assist(pool: &Pool) -> Value {
+    let mut found = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            if gravity_assist_once(&mut pool.get(), noun, verb) {
+                found = 100 * noun + verb;
+            }
+        }
+    }
+    found
+}
+
+fn gravity_assist_once(vm: &mut VM, noun: Value, verb: Value) -> bool {
+    vm.poke(1, noun).unwrap();
+    vm.poke(2, verb).unwrap();
+    vm.run_with(io::Iter(iter::empty()), Vec::new()).into_result().unwrap();
+    vm.peek(0) == 19_690_720
+}
+
+pub fn baseline_gravity_assist(program: &[Value]) -> Value {
+    let mut found = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut vm = baseline::VM::with_mem(program);
+            vm.mem_mut()[1] = noun;
+            vm.mem_mut()[2] = verb;
+            let _io = vm.setup_io();
+            vm.run().unwrap();
+            if vm.mem()[0] == 19_690_720 {
+                found = 100 * noun + verb;
+            }
+        }
+    }
+    found
+}
+
+fn permutations(xs: &mut [Value], k: usize, out: &mut Vec<Vec<Value>>) {
+    if k == xs.len() {
+        out.push(xs.to_vec());
+        return;
+    }
+    for i in k..xs.len() {
+        xs.swap(k, i);
+        permutations(xs, k + 1, out);
+        xs.swap(k, i);
+    }
+}
+
+fn phases() -> Vec<Vec<Value>> {
+    let mut phases = Vec::new();
+    permutations(&mut [5, 6, 7, 8, 9], 0, &mut phases);
+    phases
+}
+
+/// Day 7 part 2: runs every phase permutation through a feedback loop of
+/// amplifiers cloned from `template`.
+pub fn amplifiers(template: &VM) -> Value {
+    let mut best = 0;
+    for phases in phases() {
+        let mut amps = vec![template.clone(); phases.len()];
+        let mut amps = amps.iter_mut().collect::<Vec<_>>();
+        best = best.max(feedback(&mut amps, &phases));
+    }
+    best
+}
+
+/// Like `amplifiers`, with the amplifiers taken from `pool`.
+pub fn pool_amplifiers(pool: &Pool) -> Value {
+    let mut best = 0;
+    for phases in phases() {
+        let mut amps = phases.iter().map(|_| pool.get()).collect::<Vec<_>>();
+        let mut amps = amps.iter_mut().map(|vm| &mut **vm).collect::<Vec<_>>();
+        best = best.max(feedback(&mut amps, &phases));
+    }
+    best
+}
+
+fn feedback(amps: &mut [&mut VM], phases: &[Value]) -> Value {
+    for (vm, &phase) in amps.iter_mut().zip(phases) {
+        vm.provide_input(phase);
+    }
+    let mut signal = 0;
+    loop {
+        for vm in amps.iter_mut() {
+            vm.provide_input(signal);
+            signal = match vm.resume().unwrap() {
+                Event::Output(x) => x,
+                Event::Halted => return signal,
+                Event::NeedsInput => panic!("Amplifier stalled"),
+            };
+        }
+    }
+}
+
+/// The old interpreter blocks on its input channel, so every amplifier
+/// needs a thread of its own, as it did in the original solution.
+pub fn baseline_amplifiers(program: &[Value]) -> Value {
+    let mut best = 0;
+    for phases in phases() {
+        let signal = thread::scope(|s| {
+            let (first, mut rx) = mpsc::channel();
+            let mut tx = first.clone();
+            for &phase in &phases {
+                let (next_tx, next_rx) = mpsc::channel();
+                let mut vm = baseline::VM::with_mem(program);
+                vm.connect_io(rx, next_tx.clone()).unwrap();
+                s.spawn(move || vm.run().unwrap());
+                tx.send(baseline::Signal::Value(phase)).unwrap();
+                tx = next_tx;
+                rx = next_rx;
+            }
+            first.send(baseline::Signal::Value(0)).unwrap();
+            let mut signal = 0;
+            for x in rx.iter() {
+                let _ = first.send(x);
+                match x {
+                    baseline::Signal::Value(x) => signal = x,
+                    baseline::Signal::Halting => break,
+                }
+            }
+            signal
+        });
+        best = best.max(signal);
+    }
+    best
+}
+
+/// Day 9 part 2: a single long running program.
+pub fn boost(template: &VM) -> Value {
+    let mut output = Vec::new();
+    let mut vm = template.clone();
+    vm.run_with(io::Iter(iter::once(2)), &mut output).into_result().unwrap();
+    output[0]
+}
+
+pub fn baseline_boost(program: &[Value]) -> Value {
+    let mut vm = baseline::VM::with_mem(program);
+    let (input, output) = vm.setup_io();
+    input.send(baseline::Signal::Value(2)).unwrap();
+    vm.run().unwrap();
+    match output.recv().unwrap() {
+        baseline::Signal::Value(x) => x,
+        baseline::Signal::Halting => panic!("No output"),
+    }
+}
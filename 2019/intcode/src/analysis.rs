@@ -0,0 +1,540 @@
+//! Static analysis of intcode programs: basic blocks, a control-flow graph
+//! and a few patterns that are worth knowing about before running a program.
+
+use crate::{decode_at, Line, Mode, Opcode, Operand, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    fmt::Write as _,
+};
+
+/// How control gets from one block to another.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum EdgeKind {
+    Fallthrough,
+    Branch,
+    Jump,
+    Call,
+    /// From a call site to the address the callee returns to.
+    AfterCall,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// How a basic block ends.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exit {
+    /// Runs into the next block.
+    Next,
+    Halt,
+    Jump,
+    Branch,
+    Call,
+    Return,
+    /// A jump whose target is only known at runtime.
+    Indirect,
+    /// Runs into a word that does not decode, or off the end of memory.
+    Invalid,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<Line>,
+    pub exit: Exit,
+}
+
+/// A constant return address pushed relative to the base, followed by an
+/// unconditional jump.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Call {
+    pub site: usize,
+    pub target: usize,
+    pub return_to: usize,
+}
+
+/// An instruction that writes into memory that is also executed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CodeWrite {
+    pub address: usize,
+    pub target: usize,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub edges: Vec<Edge>,
+    pub calls: Vec<Call>,
+    pub returns: Vec<usize>,
+    pub indirect: Vec<usize>,
+    pub code_writes: Vec<CodeWrite>,
+    /// Instructions that write relative to the base. Where those land is
+    /// only known at runtime, so any of them may write into code as well.
+    pub relative_writes: Vec<usize>,
+    pub invalid: Vec<usize>,
+}
+
+/// Where control can go after a jump instruction.
+struct Flow {
+    exit: Exit,
+    successors: Vec<(usize, EdgeKind)>,
+}
+
+impl Cfg {
+    /// Follows every statically known path from address 0.
+    pub fn new(mem: &[Value]) -> Self {
+        let mut cfg = Cfg::default();
+        let mut lines = BTreeMap::new();
+        let mut flows = HashMap::new();
+        let mut ends = HashMap::new();
+        let mut invalid = BTreeSet::new();
+
+        let mut todo = vec![0];
+        while let Some(address) = todo.pop() {
+            if lines.contains_key(&address) || invalid.contains(&address) {
+                continue;
+            }
+            let line = match mem.get(address) {
+                Some(_) => decode_at(mem, address),
+                None => Line::Data { address, value: 0 },
+            };
+            let (opcode, operands) = match &line {
+                Line::Instruction { opcode, operands, .. } =>
+                    (*opcode, operands.clone()),
+                Line::Data { .. } => {
+                    invalid.insert(address);
+                    continue;
+                },
+            };
+
+            let end = address + line.size();
+            ends.insert(end, address);
+            lines.insert(address, line);
+            let flow = match opcode {
+                Opcode::Halt =>
+                    Some(Flow { exit: Exit::Halt, successors: vec![] }),
+                Opcode::Jit | Opcode::Jif => {
+                    let on_true = opcode == Opcode::Jit;
+                    let taken = match operands[0] {
+                        Operand { mode: Mode::Immediate, value } =>
+                            Some((value != 0) == on_true),
+                        _ => None,
+                    };
+                    let target = operands[1];
+                    match taken {
+                        // Never taken, so just an expensive no-op.
+                        Some(false) => None,
+                        Some(true) => {
+                            let (l, e) = (&lines, &ends);
+                            Some(jump(&mut cfg, l, e, address, target))
+                        },
+                        None => Some(branch(&mut cfg, address, end, target)),
+                    }
+                },
+                _ => None,
+            };
+            match flow {
+                Some(flow) => {
+                    todo.extend(flow.successors.iter().map(|&(to, _)| to));
+                    flows.insert(address, flow);
+                },
+                None => todo.push(end),
+            }
+        }
+
+        cfg.find_code_writes(&lines, &invalid);
+        cfg.build_blocks(lines, flows, &invalid);
+        cfg.invalid = invalid.into_iter().collect();
+        cfg
+    }
+
+    /// Entry points of all detected subroutines.
+    pub fn subroutines(&self) -> BTreeSet<usize> {
+        self.calls.iter().map(|c| c.target).collect()
+    }
+
+    /// The block containing `address`, if it is reachable code.
+    pub fn block_at(&self, address: usize) -> Option<&Block> {
+        let (_, block) = self.blocks.range(..=address).next_back()?;
+        if address < block.end {
+            Some(block)
+        } else {
+            None
+        }
+    }
+
+    pub fn successors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.from == block)
+    }
+
+    /// Renders the graph in Graphviz DOT format, one node per block.
+    pub fn to_dot(&self) -> String {
+        let subroutines = self.subroutines();
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph cfg {{");
+        let _ = writeln!(out, "    node [shape=box, fontname=\"monospace\"];");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for line in &block.lines {
+                let _ = write!(label, "{}\\l", line);
+            }
+            let style = if subroutines.contains(&block.start) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                "    b{} [label=\"{}\"{}];",
+                block.start,
+                label.replace('"', "\\\""),
+                style
+            );
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Branch => " [label=\"branch\"]",
+                EdgeKind::Jump => " [label=\"jump\"]",
+                EdgeKind::Call => " [label=\"call\", style=dashed]",
+                EdgeKind::AfterCall => " [label=\"return\", style=dotted]",
+            };
+            let (from, to) = (edge.from, edge.to);
+            let _ = writeln!(out, "    b{} -> b{}{};", from, to, style);
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    /// Words that do not decode where they are reached count as code, since
+    /// they are usually patched into an instruction before that.
+    fn find_code_writes(
+        &mut self,
+        lines: &BTreeMap<usize, Line>,
+        invalid: &BTreeSet<usize>,
+    ) {
+        let code = lines
+            .values()
+            .flat_map(|l| l.address()..l.address() + l.size())
+            .chain(invalid.iter().copied())
+            .collect::<BTreeSet<_>>();
+        for line in lines.values() {
+            if let Line::Instruction { address, opcode, operands } = line {
+                let target = match opcode.target().map(|t| operands[t]) {
+                    Some(Operand { mode: Mode::Positional, value }) => value,
+                    Some(Operand { mode: Mode::Relative, .. }) => {
+                        self.relative_writes.push(*address);
+                        continue;
+                    },
+                    _ => continue,
+                };
+                match usize::try_from(target) {
+                    Ok(target) if code.contains(&target) => self
+                        .code_writes
+                        .push(CodeWrite { address: *address, target }),
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    fn build_blocks(
+        &mut self,
+        lines: BTreeMap<usize, Line>,
+        flows: HashMap<usize, Flow>,
+        invalid: &BTreeSet<usize>,
+    ) {
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (&address, flow) in &flows {
+            leaders.extend(flow.successors.iter().map(|&(to, _)| to));
+            let line = &lines[&address];
+            leaders.insert(address + line.size());
+        }
+        // A jump into the operands of another instruction decodes code that
+        // overlaps it. The instruction after the overlap then starts a block
+        // of its own, which the one before it falls through to.
+        let starts = lines.keys().skip(1).map(Some).chain(Some(None));
+        for ((&address, line), next) in lines.iter().zip(starts) {
+            let end = address + line.size();
+            if next != Some(&end) {
+                leaders.insert(end);
+            }
+        }
+
+        let mut current: Option<Block> = None;
+        for (address, line) in lines {
+            let contiguous = current.as_ref().is_some_and(|b| b.end == address);
+            if !contiguous || leaders.contains(&address) {
+                if let Some(block) = current.take() {
+                    self.finish(block, &flows, &leaders, invalid);
+                }
+            }
+            let block = current.get_or_insert_with(|| Block {
+                start: address,
+                end: address,
+                lines: Vec::new(),
+                exit: Exit::Next,
+            });
+            block.end = address + line.size();
+            block.lines.push(line);
+            if flows.contains_key(&address) {
+                let block = current.take().unwrap();
+                self.finish(block, &flows, &leaders, invalid);
+            }
+        }
+        if let Some(block) = current {
+            self.finish(block, &flows, &leaders, invalid);
+        }
+    }
+
+    fn finish(
+        &mut self,
+        mut block: Block,
+        flows: &HashMap<usize, Flow>,
+        leaders: &BTreeSet<usize>,
+        invalid: &BTreeSet<usize>,
+    ) {
+        let last = block.lines.last().map_or(block.start, Line::address);
+        match flows.get(&last) {
+            Some(flow) => {
+                block.exit = flow.exit;
+                for &(to, kind) in &flow.successors {
+                    if !invalid.contains(&to) {
+                        self.edges.push(Edge { from: block.start, to, kind });
+                    }
+                }
+            },
+            None if invalid.contains(&block.end) => block.exit = Exit::Invalid,
+            None => {
+                debug_assert!(leaders.contains(&block.end));
+                let edge = Edge {
+                    from: block.start,
+                    to: block.end,
+                    kind: EdgeKind::Fallthrough,
+                };
+                self.edges.push(edge);
+            },
+        }
+        self.blocks.insert(block.start, block);
+    }
+}
+
+/// An unconditional jump at `address` to `target`, recognizing calls and
+/// returns.
+fn jump(
+    cfg: &mut Cfg,
+    lines: &BTreeMap<usize, Line>,
+    ends: &HashMap<usize, usize>,
+    address: usize,
+    target: Operand,
+) -> Flow {
+    let end = address + lines[&address].size();
+    let flow = |exit, successors| Flow { exit, successors };
+    let target = match target {
+        Operand { mode: Mode::Immediate, value } =>
+            match usize::try_from(value) {
+                Ok(target) => target,
+                Err(_) => return flow(Exit::Invalid, vec![]),
+            },
+        Operand { mode: Mode::Relative, .. } => {
+            cfg.returns.push(address);
+            return flow(Exit::Return, vec![]);
+        },
+        Operand { mode: Mode::Positional, .. } => {
+            cfg.indirect.push(address);
+            return flow(Exit::Indirect, vec![]);
+        },
+    };
+    if pushes_return_address(lines, ends, address, end) {
+        cfg.calls.push(Call { site: address, target, return_to: end });
+        let successors =
+            vec![(target, EdgeKind::Call), (end, EdgeKind::AfterCall)];
+        return flow(Exit::Call, successors);
+    }
+    flow(Exit::Jump, vec![(target, EdgeKind::Jump)])
+}
+
+/// A conditional jump at `address` to `target`.
+fn branch(cfg: &mut Cfg, address: usize, end: usize, target: Operand) -> Flow {
+    let mut successors = vec![(end, EdgeKind::Fallthrough)];
+    if target.mode != Mode::Immediate {
+        cfg.indirect.push(address);
+        return Flow { exit: Exit::Indirect, successors };
+    }
+    if let Ok(target) = usize::try_from(target.value) {
+        successors.insert(0, (target, EdgeKind::Branch));
+    }
+    Flow { exit: Exit::Branch, successors }
+}
+
+/// Looks back through the straight-line code before the jump at `address`
+/// for an instruction that stores `return_to` relative to the base.
+fn pushes_return_address(
+    lines: &BTreeMap<usize, Line>,
+    ends: &HashMap<usize, usize>,
+    address: usize,
+    return_to: usize,
+) -> bool {
+    let mut at = address;
+    for _ in 0..8 {
+        let start = match ends.get(&at) {
+            Some(&start) => start,
+            None => return false,
+        };
+        if let Line::Instruction { opcode, operands, .. } = &lines[&start] {
+            let constant = match (opcode, &operands[..]) {
+                (Opcode::Add, [a, b, t]) | (Opcode::Mul, [a, b, t])
+                    if a.mode == Mode::Immediate
+                        && b.mode == Mode::Immediate
+                        && t.mode == Mode::Relative =>
+                    if *opcode == Opcode::Add {
+                        a.value.checked_add(b.value)
+                    } else {
+                        a.value.checked_mul(b.value)
+                    },
+                (Opcode::Jit, _) | (Opcode::Jif, _) | (Opcode::Halt, _) =>
+                    return false,
+                _ => None,
+            };
+            if constant == Value::try_from(return_to).ok() {
+                return true;
+            }
+        }
+        at = start;
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    #[test_log::new]
+    fn blocks_and_calls() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+                    arb  #stack
+                    add  #back, #0, rb+0
+                    jt   #1, #double
+            back:   out  [x]
+                    jf   [x], #done
+                    add  #0, #0, [patch+1]
+            patch:  out  #7
+            done:   hlt
+            double: mul  [x], #2, [x]
+                    jt   #1, rb+0
+            x:      db   21
+            stack:  db   0
+            "#,
+        )?;
+
+        let cfg = Cfg::new(&program);
+        let blocks = cfg
+            .blocks
+            .values()
+            .map(|b| (b.start, b.end, b.exit))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [
+                (0, 9, Exit::Call),
+                (9, 14, Exit::Branch),
+                (14, 20, Exit::Next),
+                (20, 21, Exit::Halt),
+                (21, 28, Exit::Return),
+            ]
+        );
+
+        let mut edges = cfg
+            .edges
+            .iter()
+            .map(|e| (e.from, e.to, e.kind))
+            .collect::<Vec<_>>();
+        edges.sort();
+        assert_eq!(
+            edges,
+            [
+                (0, 9, EdgeKind::AfterCall),
+                (0, 21, EdgeKind::Call),
+                (9, 14, EdgeKind::Fallthrough),
+                (9, 20, EdgeKind::Branch),
+                (14, 20, EdgeKind::Fallthrough),
+            ]
+        );
+
+        assert_eq!(cfg.calls, [Call { site: 6, target: 21, return_to: 9 }]);
+        assert_eq!(cfg.returns, [25]);
+        assert_eq!(cfg.code_writes, [CodeWrite { address: 14, target: 19 }]);
+        assert_eq!(cfg.relative_writes, [2]);
+        assert!(cfg.indirect.is_empty() && cfg.invalid.is_empty());
+        assert_eq!(cfg.block_at(16).map(|b| b.start), Some(14));
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b21 [label=\"    21: mul "));
+        assert!(dot.contains("style=bold"));
+        assert!(dot.contains("b0 -> b21 [label=\"call\", style=dashed];"));
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn overlapping_code() {
+        // Jumps to the 99 in the operands of the first instruction.
+        let cfg = Cfg::new(&[1101, 0, 99, 20, 1105, 1, 2]);
+        let blocks = cfg
+            .blocks
+            .values()
+            .map(|b| (b.start, b.end, b.exit))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [(0, 4, Exit::Next), (2, 3, Exit::Halt), (4, 7, Exit::Jump),]
+        );
+        assert_eq!(
+            cfg.edges,
+            [
+                Edge { from: 0, to: 4, kind: EdgeKind::Fallthrough },
+                Edge { from: 4, to: 2, kind: EdgeKind::Jump },
+            ]
+        );
+
+        // The overlapping instruction ends where the first one does.
+        let cfg = Cfg::new(&[1101, 0, 104, 20, 1105, 1, 2]);
+        let blocks = cfg
+            .blocks
+            .values()
+            .map(|b| (b.start, b.end, b.exit))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            [(0, 4, Exit::Next), (2, 4, Exit::Next), (4, 7, Exit::Jump),]
+        );
+        assert_eq!(
+            cfg.edges,
+            [
+                Edge { from: 0, to: 4, kind: EdgeKind::Fallthrough },
+                Edge { from: 2, to: 4, kind: EdgeKind::Fallthrough },
+                Edge { from: 4, to: 2, kind: EdgeKind::Jump },
+            ]
+        );
+    }
+
+    #[test_log::new]
+    fn puzzle_input() -> aoc::Result<()> {
+        const ROBOT: &str = include_str!("../../day11/input.txt");
+        let vm = crate::VM::with_program(ROBOT)?;
+        let cfg = Cfg::new(vm.mem());
+        assert!(!cfg.calls.is_empty() && !cfg.returns.is_empty());
+        for edge in &cfg.edges {
+            assert!(cfg.blocks.contains_key(&edge.to), "{:?}", edge);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! Line oriented access to programs that talk ASCII.
+
+use crate::{Error, Event, Value, VM};
+use std::{
+    convert::TryFrom,
+    io::{BufRead, Write},
+};
+
+/// A single piece of program output: either a run of ASCII text or a value
+/// outside of the ASCII range, such as a puzzle answer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Output {
+    Text(String),
+    Value(Value),
+}
+
+/// Everything a program printed before it asked for input or halted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Prompt {
+    pub output: Vec<Output>,
+    pub halted: bool,
+}
+
+pub struct Terminal {
+    vm: VM,
+}
+
+impl Prompt {
+    pub fn text(&self) -> String {
+        self.output
+            .iter()
+            .filter_map(|o| match o {
+                Output::Text(s) => Some(s.as_str()),
+                Output::Value(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.output.iter().filter_map(|o| match *o {
+            Output::Value(x) => Some(x),
+            Output::Text(_) => None,
+        })
+    }
+
+    fn push(&mut self, x: Value) {
+        let c = match u8::try_from(x) {
+            Ok(b) if b.is_ascii() => char::from(b),
+            _ => return self.output.push(Output::Value(x)),
+        };
+        match self.output.last_mut() {
+            Some(Output::Text(s)) => s.push(c),
+            _ => self.output.push(Output::Text(c.to_string())),
+        }
+    }
+}
+
+impl Terminal {
+    pub fn new(vm: VM) -> Self {
+        Terminal { vm }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> VM {
+        self.vm
+    }
+
+    /// Queues `line` followed by a newline as input.
+    pub fn send_line(&mut self, line: &str) -> aoc::Result<()> {
+        if !line.is_ascii() {
+            return aoc::err!("Cannot send non-ASCII line: {:?}", line);
+        }
+        for b in line.bytes().chain(Some(b'\n')) {
+            self.vm.provide_input(Value::from(b));
+        }
+        Ok(())
+    }
+
+    /// Runs the program until it runs out of input or halts.
+    pub fn read_until_prompt(&mut self) -> Result<Prompt, Error> {
+        let mut prompt = Prompt::default();
+        loop {
+            match self.vm.resume()? {
+                Event::Output(x) => prompt.push(x),
+                Event::NeedsInput => return Ok(prompt),
+                Event::Halted => {
+                    prompt.halted = true;
+                    return Ok(prompt);
+                },
+            }
+        }
+    }
+
+    /// Bridges the program to a human: prompts are written to `output` and
+    /// every line read from `input` is sent back, until the program halts or
+    /// `input` runs dry.
+    pub fn interact(
+        &mut self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> aoc::Result<()> {
+        loop {
+            let prompt = self.read_until_prompt()?;
+            for o in &prompt.output {
+                match o {
+                    Output::Text(s) => write!(output, "{}", s)?,
+                    Output::Value(x) => writeln!(output, "\n[{}]", x)?,
+                }
+            }
+            output.flush()?;
+            if prompt.halted {
+                return Ok(());
+            }
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            self.send_line(line.trim_end_matches(&['\r', '\n'][..]))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    #[test_log::new]
+    fn echo_with_answer() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+            ; print the prompt, then echo one line and print 1000
+                    arb  #prompt
+            print:  jf   rb, #read
+                    out  rb
+                    arb  #1
+                    jt   #1, #print
+            read:   in   [c]
+                    out  [c]
+                    eq   [c], #10, [t]
+                    jf   [t], #read
+                    out  #1000
+                    hlt
+            c:      db   0
+            t:      db   0
+            prompt: db   "> ", 0
+            "#,
+        )?;
+
+        let mut term = Terminal::new(VM::with_mem(&program));
+        let prompt = term.read_until_prompt()?;
+        assert_eq!(prompt.output, [Output::Text("> ".to_owned())]);
+        assert!(!prompt.halted);
+
+        term.send_line("hi")?;
+        let prompt = term.read_until_prompt()?;
+        assert_eq!(prompt.text(), "hi\n");
+        assert_eq!(prompt.values().collect::<Vec<_>>(), [1000]);
+        assert!(prompt.halted);
+        assert!(term.send_line("héllo").is_err());
+
+        let mut term = Terminal::new(VM::with_mem(&program));
+        let mut screen = Vec::new();
+        term.interact(&b"hey\n"[..], &mut screen)?;
+        assert_eq!(String::from_utf8(screen)?, "> hey\n\n[1000]\n");
+        Ok(())
+    }
+}
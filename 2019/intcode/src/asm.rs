@@ -0,0 +1,343 @@
+//! A small assembly language for intcode, mirroring the disassembler's
+//! listing format:
+//!
+//! ```text
+//! ; echo input until a zero is read
+//! loop:   in   [x]
+//!         jf   [x], #end
+//!         out  [x]
+//!         jt   #1, #loop
+//! end:    hlt
+//! x:      db   0
+//! msg:    db   "hi", 10
+//! ```
+//!
+//! Operands are `[addr]` (positional), `#value` (immediate) or `rb+off`
+//! (relative). Any value may be a number, a label, or `label+offset`.
+//! A numeric label such as `12:` asserts the current address, so the output
+//! of `disassemble` assembles back to the original program.
+
+use crate::{encode, Mode, Opcode, Value};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Value),
+    Label(String, Value),
+}
+
+enum Item {
+    Instruction(Opcode, Vec<(Mode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+pub fn assemble(source: &str) -> aoc::Result<Vec<Value>> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (n, line) in source.lines().enumerate() {
+        let n = n + 1;
+        let mut line = strip_comment(line).trim();
+        while let Some((label, rest)) = split_label(line) {
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return aoc::err!(
+                        "line {}: expected address {}, but is at {}",
+                        n,
+                        expected,
+                        address
+                    );
+                }
+            } else if labels.insert(label.to_owned(), address).is_some() {
+                return aoc::err!("line {}: duplicate label '{}'", n, label);
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let args = split_args(rest).map_err(|e| line_err(n, e))?;
+        let item = if mnemonic == "db" {
+            parse_data(&args).map_err(|e| line_err(n, e))?
+        } else {
+            parse_instruction(mnemonic, &args).map_err(|e| line_err(n, e))?
+        };
+
+        address += match &item {
+            Item::Instruction(_, operands) => operands.len() + 1,
+            Item::Data(values) => values.len(),
+        };
+        items.push((n, item));
+    }
+
+    let resolve = |n: usize, expr: &Expr| -> aoc::Result<Value> {
+        match expr {
+            Expr::Literal(x) => Ok(*x),
+            Expr::Label(label, offset) => match labels.get(label) {
+                Some(&address) => Ok(address as Value + offset),
+                None => aoc::err!("line {}: undefined label '{}'", n, label),
+            },
+        }
+    };
+
+    let mut mem = Vec::with_capacity(address);
+    for (n, item) in &items {
+        match item {
+            Item::Instruction(opcode, operands) => {
+                let modes =
+                    operands.iter().map(|(m, _)| *m).collect::<Vec<_>>();
+                mem.push(encode(*opcode, &modes));
+                for (_, expr) in operands {
+                    mem.push(resolve(*n, expr)?);
+                }
+            },
+            Item::Data(values) =>
+                for expr in values {
+                    mem.push(resolve(*n, expr)?);
+                },
+        }
+    }
+
+    Ok(mem)
+}
+
+fn line_err(
+    n: usize,
+    e: Box<dyn std::error::Error>,
+) -> Box<dyn std::error::Error> {
+    aoc::format_err!("line {}: {}", n, e)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let i = line.find(':')?;
+    let label = line[..i].trim();
+    if !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        Some((label, &line[i + 1..]))
+    } else {
+        None
+    }
+}
+
+fn split_args(s: &str) -> aoc::Result<Vec<&str>> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                args.push(s[start..i].trim());
+                start = i + 1;
+            },
+            _ => (),
+        }
+    }
+    if in_string {
+        return aoc::err!("unterminated string");
+    }
+
+    let last = s[start..].trim();
+    if !last.is_empty() || !args.is_empty() {
+        args.push(last);
+    }
+    if args.iter().any(|a| a.is_empty()) {
+        return aoc::err!("empty operand");
+    }
+    Ok(args)
+}
+
+fn parse_instruction(mnemonic: &str, args: &[&str]) -> aoc::Result<Item> {
+    let opcode = mnemonic.parse::<Opcode>()?;
+    if args.len() != opcode.arity() {
+        return aoc::err!(
+            "'{}' takes {} operand(s), got {}",
+            mnemonic,
+            opcode.arity(),
+            args.len()
+        );
+    }
+
+    let operands = args
+        .iter()
+        .map(|a| parse_operand(a))
+        .collect::<aoc::Result<Vec<_>>>()?;
+    if let Some(t) = opcode.target() {
+        if operands[t].0 == Mode::Immediate {
+            return aoc::err!("'{}' cannot write to an immediate", mnemonic);
+        }
+    }
+    Ok(Item::Instruction(opcode, operands))
+}
+
+fn parse_operand(s: &str) -> aoc::Result<(Mode, Expr)> {
+    if s.starts_with('[') && s.ends_with(']') {
+        Ok((Mode::Positional, parse_expr(&s[1..s.len() - 1])?))
+    } else if let Some(rest) = s.strip_prefix('#') {
+        Ok((Mode::Immediate, parse_expr(rest)?))
+    } else if let Some(rest) = s.strip_prefix("rb") {
+        let rest = rest.trim();
+        let expr = if rest.is_empty() {
+            Expr::Literal(0)
+        } else if let Some(offset) = rest.strip_prefix('+') {
+            parse_expr(offset)?
+        } else if rest.starts_with('-') {
+            match parse_expr(rest)? {
+                Expr::Literal(x) => Expr::Literal(x),
+                Expr::Label(..) =>
+                    return aoc::err!("cannot negate label in '{}'", s),
+            }
+        } else {
+            return aoc::err!("invalid relative operand '{}'", s);
+        };
+        Ok((Mode::Relative, expr))
+    } else {
+        aoc::err!("invalid operand '{}'", s)
+    }
+}
+
+fn parse_expr(s: &str) -> aoc::Result<Expr> {
+    let s = s.trim();
+    if let Ok(x) = s.parse::<Value>() {
+        return Ok(Expr::Literal(x));
+    }
+
+    let (label, offset) = match s.rfind(['+', '-']) {
+        Some(i) => (s[..i].trim(), s[i..].replace(' ', "").parse::<Value>()?),
+        None => (s, 0),
+    };
+    let valid = label
+        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return aoc::err!("invalid value '{}'", s);
+    }
+    Ok(Expr::Label(label.to_owned(), offset))
+}
+
+fn parse_data(args: &[&str]) -> aoc::Result<Item> {
+    let mut values = Vec::new();
+    for arg in args {
+        if arg.starts_with('"') && arg.ends_with('"') && arg.len() >= 2 {
+            let mut chars = arg[1..arg.len() - 1].chars();
+            while let Some(c) = chars.next() {
+                let c = match c {
+                    '\\' => match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(c @ '\\') | Some(c @ '"') => c,
+                        c => return aoc::err!("invalid escape '\\{:?}'", c),
+                    },
+                    c => c,
+                };
+                values.push(Expr::Literal(Value::from(u32::from(c))));
+            }
+        } else {
+            values.push(parse_expr(arg)?);
+        }
+    }
+    Ok(Item::Data(values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{disassemble, Event, VM};
+
+    #[test_log::new]
+    fn echo() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+            ; echo input until a zero is read
+            loop:   in   [x]
+                    jf   [x], #end
+                    out  [x]
+                    jt   #1, #loop
+            end:    hlt
+            x:      db   0
+            "#,
+        )?;
+        assert_eq!(program, [3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0]);
+
+        let mut vm = VM::with_mem(&program);
+        vm.provide_input(7);
+        vm.provide_input(0);
+        assert_eq!(vm.resume()?, Event::Output(7));
+        assert_eq!(vm.resume()?, Event::Halted);
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn data_and_relative() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+                    arb  #msg
+            next:   jf   rb, #done
+                    out  rb+0
+                    arb  #1
+                    jt   #1, #next
+            done:   hlt
+            msg:    db   "hi;\"", 10, 0
+            "#,
+        )?;
+
+        let mut vm = VM::with_mem(&program);
+        let mut output = String::new();
+        while let Event::Output(x) = vm.resume()? {
+            output.push(x as u8 as char);
+        }
+        assert_eq!(output, "hi;\"\n");
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn round_trip() -> aoc::Result<()> {
+        const DIAGNOSTIC: &str = include_str!("../../day05/input.txt");
+
+        let vm = VM::with_program(DIAGNOSTIC)?;
+        let listing = disassemble(vm.mem())
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(assemble(&listing)?, vm.mem());
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn errors() {
+        assert!(assemble("add [1], [2]").is_err());
+        assert!(assemble("in #1").is_err());
+        assert!(assemble("jt #1, #nowhere").is_err());
+        assert!(assemble("a: hlt\na: hlt").is_err());
+        assert!(assemble("3: hlt").is_err());
+        assert!(assemble("db \"oops").is_err());
+        assert!(assemble("nop").is_err());
+    }
+}
@@ -0,0 +1,14 @@
+use intcode::{ascii::Terminal, VM};
+use std::{env, fs, io};
+
+fn main() -> aoc::Result<()> {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => return aoc::err!("usage: ascii <program>"),
+    };
+
+    let vm = VM::with_program(&fs::read_to_string(path)?)?;
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Terminal::new(vm).interact(stdin.lock(), stdout.lock())
+}
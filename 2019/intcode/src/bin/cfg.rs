@@ -0,0 +1,46 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+};
+
+fn main() -> aoc::Result<()> {
+    let program = match env::args().nth(1) {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        },
+    };
+
+    let vm = intcode::VM::with_program(&program)?;
+    let cfg = intcode::analysis::Cfg::new(vm.mem());
+    let stderr = io::stderr();
+    let mut e = stderr.lock();
+    for call in &cfg.calls {
+        writeln!(
+            e,
+            "call at {} to {}, returning to {}",
+            call.site, call.target, call.return_to
+        )?;
+    }
+    for address in &cfg.returns {
+        writeln!(e, "return at {}", address)?;
+    }
+    for address in &cfg.indirect {
+        writeln!(e, "indirect jump at {}", address)?;
+    }
+    for write in &cfg.code_writes {
+        let (target, address) = (write.target, write.address);
+        writeln!(e, "write into code at {} by {}", target, address)?;
+    }
+    for address in &cfg.relative_writes {
+        writeln!(e, "write relative to rb at {}", address)?;
+    }
+    for address in &cfg.invalid {
+        writeln!(e, "invalid instruction reached at {}", address)?;
+    }
+
+    write!(io::stdout(), "{}", cfg.to_dot())?;
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use intcode::{decode_at, Event, Value, VM};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    env, fs,
+    io::{self, BufRead, Write},
+};
+
+const HELP: &str = "\
+s, step [n]         execute n instructions (default 1)
+c, continue         run until a breakpoint, watchpoint, input request or halt
+bs, back [n]        undo n instructions (default 1)
+rc, rcontinue       run backwards to the previous breakpoint
+b, break <addr>     break when ip reaches addr
+w, watch <addr>     break when the value at addr changes
+d, delete <addr>    remove the breakpoint and watchpoint on addr
+l, list [addr] [n]  disassemble n instructions from addr (default ip, 10)
+x, mem <addr> [n]   show n memory cells from addr (default 1)
+set <addr> <value>  write value to addr
+ip [addr]           show or set the instruction pointer
+rb [value]          show or set the relative base
+i, input <v>...     queue input values
+a, ascii <text>     queue text followed by a newline as input
+info                show registers, pending input and breakpoints
+reset               reload the program and clear pending input
+q, quit             exit the debugger
+An empty line repeats the previous command.";
+
+/// Number of instructions that can be undone.
+const UNDO_DEPTH: usize = 1_000_000;
+
+enum Stop {
+    Event(Event),
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: Value, new: Value },
+}
+
+struct Debugger {
+    program: Vec<Value>,
+    vm: VM,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, Value>,
+}
+
+impl Debugger {
+    fn new(program: Vec<Value>) -> Self {
+        let mut vm = VM::with_mem(&program);
+        vm.enable_undo(UNDO_DEPTH);
+        Debugger {
+            program,
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    fn step(&mut self) -> aoc::Result<Option<Stop>> {
+        // A fault leaves ip on the faulting instruction, so continuing
+        // afterwards runs into the same fault again.
+        match self.vm.step()? {
+            Some(Event::Output(x)) => print_output(x),
+            Some(e) => return Ok(Some(Stop::Event(e))),
+            None => (),
+        }
+
+        for (&address, old) in &mut self.watchpoints {
+            let new = self.vm.peek(address);
+            if new != *old {
+                let stop = Stop::Watchpoint { address, old: *old, new };
+                *old = new;
+                return Ok(Some(stop));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn run_to_stop(&mut self) -> aoc::Result<Stop> {
+        loop {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+            if self.breakpoints.contains(&self.vm.ip()) {
+                return Ok(Stop::Breakpoint(self.vm.ip()));
+            }
+        }
+    }
+
+    /// Brings watchpoints up to date after memory changed behind their back.
+    fn refresh_watchpoints(&mut self) {
+        for (&address, old) in &mut self.watchpoints {
+            *old = self.vm.peek(address);
+        }
+    }
+
+    fn report(&self, stop: Option<Stop>) {
+        match stop {
+            Some(Stop::Event(Event::NeedsInput)) =>
+                println!("waiting for input"),
+            Some(Stop::Event(Event::Halted)) => println!("halted"),
+            Some(Stop::Event(Event::Output(_))) => (),
+            Some(Stop::Breakpoint(ip)) => println!("breakpoint at {}", ip),
+            Some(Stop::Watchpoint { address, old, new }) =>
+                println!("watchpoint [{}]: {} -> {}", address, old, new),
+            None => (),
+        }
+        self.list(self.vm.ip(), 1);
+    }
+
+    fn list(&self, mut address: usize, n: usize) {
+        let mem = self.vm.mem();
+        for _ in 0..n {
+            if address >= mem.len() {
+                break;
+            }
+            let line = decode_at(mem, address);
+            let ip = if address == self.vm.ip() { '>' } else { ' ' };
+            let bp =
+                if self.breakpoints.contains(&address) { '*' } else { ' ' };
+            println!("{}{}{}", ip, bp, line);
+            address += line.size();
+        }
+    }
+
+    fn info(&self) {
+        println!("ip = {}", self.vm.ip());
+        println!("rb = {}", self.vm.rp());
+        println!("steps = {}", self.vm.instructions_executed());
+        println!("input = {:?}", self.vm.pending_input());
+        println!("breakpoints = {:?}", self.breakpoints);
+        println!("watchpoints = {:?}", self.watchpoints.keys());
+    }
+
+    fn execute(&mut self, cmd: &str, args: &[&str]) -> aoc::Result<bool> {
+        match (cmd, args) {
+            ("s", _) | ("step", _) => {
+                let n = match args.first() {
+                    Some(n) => n.parse()?,
+                    None => 1,
+                };
+                let mut stop = None;
+                for _ in 0..n {
+                    stop = self.step()?;
+                    if stop.is_some() {
+                        break;
+                    }
+                }
+                self.report(stop);
+            },
+            ("c", []) | ("continue", []) => {
+                let stop = self.run_to_stop()?;
+                self.report(Some(stop));
+            },
+            ("bs", _) | ("back", _) => {
+                let n = match args.first() {
+                    Some(n) => n.parse()?,
+                    None => 1,
+                };
+                let undone = self.vm.step_back(n);
+                self.refresh_watchpoints();
+                if undone < n {
+                    println!("no more history");
+                }
+                self.report(None);
+            },
+            ("rc", []) | ("rcontinue", []) => {
+                let breakpoints = &self.breakpoints;
+                let found =
+                    self.vm.rewind_until(|ip| breakpoints.contains(&ip));
+                self.refresh_watchpoints();
+                if !found {
+                    println!("no more history");
+                }
+                self.report(None);
+            },
+            ("b", [addr]) | ("break", [addr]) => {
+                self.breakpoints.insert(addr.parse()?);
+            },
+            ("w", [addr]) | ("watch", [addr]) => {
+                let address = addr.parse()?;
+                self.watchpoints.insert(address, self.vm.peek(address));
+            },
+            ("d", [addr]) | ("delete", [addr]) => {
+                let address = addr.parse()?;
+                self.breakpoints.remove(&address);
+                self.watchpoints.remove(&address);
+            },
+            ("l", _) | ("list", _) => {
+                let address = match args.first() {
+                    Some(addr) => addr.parse()?,
+                    None => self.vm.ip(),
+                };
+                let n = match args.get(1) {
+                    Some(n) => n.parse()?,
+                    None => 10,
+                };
+                self.list(address, n);
+            },
+            ("x", [addr, ..]) | ("mem", [addr, ..]) => {
+                let address: usize = addr.parse()?;
+                let n = match args.get(1) {
+                    Some(n) => n.parse()?,
+                    None => 1,
+                };
+                for a in address..address.saturating_add(n) {
+                    println!("[{}] = {}", a, self.vm.peek(a));
+                }
+            },
+            ("set", [addr, value]) => {
+                let address = addr.parse()?;
+                self.vm.poke(address, value.parse()?)?;
+                if let Some(old) = self.watchpoints.get_mut(&address) {
+                    *old = self.vm.peek(address);
+                }
+            },
+            ("ip", []) => println!("ip = {}", self.vm.ip()),
+            ("ip", [addr]) => self.vm.set_ip(addr.parse()?),
+            ("rb", []) => println!("rb = {}", self.vm.rp()),
+            ("rb", [value]) => self.vm.set_rp(value.parse()?),
+            ("i", _) | ("input", _) =>
+                for v in args {
+                    self.vm.provide_input(v.parse()?);
+                },
+            ("a", _) | ("ascii", _) => {
+                for b in args.join(" ").bytes().chain(Some(b'\n')) {
+                    self.vm.provide_input(Value::from(b));
+                }
+            },
+            ("info", []) => self.info(),
+            ("reset", []) => {
+                self.vm = VM::with_mem(&self.program);
+                self.vm.enable_undo(UNDO_DEPTH);
+                self.refresh_watchpoints();
+                self.report(None);
+            },
+            ("q", []) | ("quit", []) => return Ok(false),
+            ("h", []) | ("help", []) => println!("{}", HELP),
+            _ => return aoc::err!("invalid command, try 'help'"),
+        }
+        Ok(true)
+    }
+}
+
+fn print_output(x: Value) {
+    match u8::try_from(x) {
+        Ok(b) if b.is_ascii_graphic() || b == b' ' =>
+            println!("output: {} '{}'", x, b as char),
+        _ => println!("output: {}", x),
+    }
+}
+
+fn main() -> aoc::Result<()> {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => return aoc::err!("usage: debugger <program>"),
+    };
+    let vm = VM::with_program(&fs::read_to_string(path)?)?;
+    let mut debugger = Debugger::new(vm.mem().to_vec());
+    debugger.report(None);
+
+    let stdin = io::stdin();
+    let mut previous = String::new();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = previous.clone();
+        }
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (cmd, args) = match words.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => continue,
+        };
+        match debugger.execute(cmd, args) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+        previous = line;
+    }
+
+    Ok(())
+}
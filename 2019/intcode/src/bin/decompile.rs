@@ -0,0 +1,15 @@
+use aoc::err;
+use std::{env, fs};
+
+/// Usage: decompile <program> <output>
+fn main() -> aoc::Result<()> {
+    let mut args = env::args().skip(1);
+    let (input, output) = match (args.next(), args.next()) {
+        (Some(input), Some(output)) => (input, output),
+        _ => return err!("usage: decompile <program> <output>"),
+    };
+
+    let vm = intcode::VM::with_program(&fs::read_to_string(input)?)?;
+    fs::write(output, intcode::decompile(vm.mem()))?;
+    Ok(())
+}
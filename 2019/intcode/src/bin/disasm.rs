@@ -0,0 +1,23 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+};
+
+fn main() -> aoc::Result<()> {
+    let program = match env::args().nth(1) {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        },
+    };
+
+    let vm = intcode::VM::with_program(&program)?;
+    let stdout = io::stdout();
+    let mut w = stdout.lock();
+    for line in intcode::disassemble(vm.mem()) {
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
+}
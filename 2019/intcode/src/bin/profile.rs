@@ -0,0 +1,31 @@
+use intcode::{Event, Value, VM};
+use std::{env, fs};
+
+fn main() -> aoc::Result<()> {
+    let mut args = env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => return aoc::err!("usage: profile <program> [input]..."),
+    };
+
+    let mut vm = VM::with_program(&fs::read_to_string(path)?)?;
+    for arg in args {
+        vm.provide_input(arg.parse::<Value>()?);
+    }
+
+    vm.enable_profiling();
+    loop {
+        match vm.resume()? {
+            Event::Output(x) => println!("output: {}", x),
+            Event::NeedsInput => {
+                println!("stopped: program is waiting for input");
+                break;
+            },
+            Event::Halted => break,
+        }
+    }
+
+    let profile = vm.take_profile().unwrap();
+    print!("\n{}", profile.report(vm.mem(), 20));
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use crate::{decode_word, Error, Extensions, Memory, Mode, Opcode, Word};
+use std::sync::Arc;
+
+/// An instruction with its opcode, parameter modes and raw parameters
+/// already pulled out of memory.
+#[derive(Clone, Debug)]
+pub(crate) struct Decoded<W> {
+    pub opcode: Opcode,
+    pub modes: [Mode; 3],
+    pub params: [W; 3],
+}
+
+/// Decoded instructions keyed by the address they start at. The table is
+/// shared between clones of a machine; writes only mark the instructions they
+/// touch as dirty, so a shared table never has to be copied. It is only
+/// allocated once something is cached.
+#[derive(Clone, Debug)]
+pub(crate) struct Cache<W> {
+    enabled: bool,
+    slots: Option<Arc<Vec<Option<Decoded<W>>>>>,
+    /// Bitset of shared slots that no longer match memory.
+    dirty: Vec<u64>,
+}
+
+impl<W> Default for Cache<W> {
+    fn default() -> Self {
+        Cache { enabled: true, slots: None, dirty: Vec::new() }
+    }
+}
+
+impl<W: Word> Decoded<W> {
+    #[inline(always)]
+    pub fn new(
+        mem: &Memory<W>,
+        ip: usize,
+        custom: &Extensions<W>,
+    ) -> Result<Self, Error> {
+        let word = mem.fetch(ip).ok_or(Error::IpOutOfBounds { ip })?;
+        let word = word.to_value().ok_or(Error::Overflow { ip })?;
+        let (opcode, modes) = match decode_word(ip, word) {
+            Err(Error::UnknownOpcode { .. }) => custom.decode(ip, word)?,
+            decoded => decoded?,
+        };
+        let mut params = <[W; 3]>::default();
+        for (i, p) in params.iter_mut().take(opcode.arity()).enumerate() {
+            *p = mem.fetch(ip + 1 + i).ok_or(Error::IpOutOfBounds { ip })?;
+        }
+        Ok(Decoded { opcode, modes, params })
+    }
+}
+
+impl<W: Word> Cache<W> {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = None;
+        self.dirty.clear();
+    }
+
+    /// Decodes every address in the dense part of `mem` that holds a valid
+    /// instruction, whether or not it is ever executed.
+    pub fn compile(&mut self, mem: &Memory<W>, custom: &Extensions<W>) {
+        if !self.enabled {
+            return;
+        }
+        let slots = (0..mem.dense().len())
+            .map(|ip| Decoded::new(mem, ip, custom).ok())
+            .collect();
+        self.slots = Some(Arc::new(slots));
+        self.dirty.clear();
+    }
+
+    #[inline]
+    pub fn get(&self, ip: usize) -> Option<&Decoded<W>> {
+        let dirty = self.dirty.get(ip / 64).map_or(0, |d| d >> (ip % 64));
+        if dirty & 1 != 0 {
+            return None;
+        }
+        self.slots.as_ref()?.get(ip)?.as_ref()
+    }
+
+    /// Caches `decoded` unless the table is shared with another machine or
+    /// `ip` lies outside of the first `limit` addresses.
+    pub fn insert(&mut self, ip: usize, decoded: Decoded<W>, limit: usize) {
+        if !self.enabled || ip >= limit {
+            return;
+        }
+        // Start a table of our own if there is none yet.
+        let slots = self.slots.get_or_insert_with(Arc::default);
+        let slots = match Arc::get_mut(slots) {
+            Some(slots) => slots,
+            None => return,
+        };
+        if ip >= slots.len() {
+            slots.resize(ip + 1, None);
+        }
+        slots[ip] = Some(decoded);
+        if let Some(d) = self.dirty.get_mut(ip / 64) {
+            *d &= !(1 << (ip % 64));
+        }
+    }
+
+    /// Forgets every instruction that `address` is part of.
+    #[inline]
+    pub fn invalidate(&mut self, address: usize) {
+        let slots = match &mut self.slots {
+            Some(slots) => slots,
+            None => return,
+        };
+        let first = address.saturating_sub(3);
+        if first >= slots.len() {
+            return;
+        }
+        let last = address.min(slots.len() - 1);
+        if slots[first..=last].iter().all(Option::is_none) {
+            return;
+        }
+        if let Some(slots) = Arc::get_mut(slots) {
+            for slot in &mut slots[first..=last] {
+                *slot = None;
+            }
+            return;
+        }
+        if self.dirty.len() <= last / 64 {
+            self.dirty.resize(last / 64 + 1, 0);
+        }
+        for ip in first..=last {
+            self.dirty[ip / 64] |= 1 << (ip % 64);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    decode_modes, Decoded, Error, Event, Mode, Opcode, Value, Word, VM,
+};
+use std::{collections::HashMap, convert::TryFrom, sync::Arc};
+
+type Handler<W> = dyn Fn(&mut Operands<'_, W>) -> Result<Option<Event<W>>, Error>
+    + Send
+    + Sync;
+
+struct Custom<W> {
+    arity: usize,
+    handler: Arc<Handler<W>>,
+}
+
+/// Instructions registered on top of the built-in ones, shared between
+/// clones of a machine. The table is only allocated once something is
+/// registered, which keeps creating machines cheap.
+pub(crate) struct Extensions<W> {
+    customs: Option<Arc<HashMap<u8, Custom<W>>>>,
+}
+
+/// The view a custom instruction has of the machine executing it.
+pub struct Operands<'a, W = Value> {
+    vm: &'a mut VM<W>,
+    ip: usize,
+    instr: &'a Decoded<W>,
+}
+
+impl<W> Clone for Custom<W> {
+    fn clone(&self) -> Self {
+        Custom { arity: self.arity, handler: self.handler.clone() }
+    }
+}
+
+impl<W> Default for Extensions<W> {
+    fn default() -> Self {
+        Extensions { customs: None }
+    }
+}
+
+impl<W> Clone for Extensions<W> {
+    fn clone(&self) -> Self {
+        Extensions { customs: self.customs.clone() }
+    }
+}
+
+impl<W: Word> Extensions<W> {
+    pub fn register(
+        &mut self,
+        code: Value,
+        arity: usize,
+        handler: Arc<Handler<W>>,
+    ) -> aoc::Result<()> {
+        if Opcode::try_from(code).is_ok() {
+            return aoc::err!("Opcode {} is built in", code);
+        }
+        let code = match u8::try_from(code) {
+            Ok(code) if code < 100 => code,
+            _ => return aoc::err!("Opcode {} is not in 0..100", code),
+        };
+        if arity > 3 {
+            return aoc::err!("Opcode {} has more than 3 parameters", code);
+        }
+        let custom = Custom { arity, handler };
+        let customs = self.customs.get_or_insert_with(Arc::default);
+        Arc::make_mut(customs).insert(code, custom);
+        Ok(())
+    }
+
+    /// Decodes an instruction word the built-in opcodes did not recognize.
+    pub fn decode(
+        &self,
+        ip: usize,
+        word: Value,
+    ) -> Result<(Opcode, [Mode; 3]), Error> {
+        let code = u8::try_from(word % 100).ok();
+        let customs = self.customs.as_deref();
+        match code.and_then(|code| Some((code, customs?.get(&code)?))) {
+            Some((code, custom)) => {
+                let arity = custom.arity as u8;
+                let opcode = Opcode::Custom { code, arity };
+                Ok((opcode, decode_modes(ip, word)?))
+            },
+            None => Err(Error::UnknownOpcode { ip, value: word }),
+        }
+    }
+
+    pub fn handler(&self, code: u8) -> Arc<Handler<W>> {
+        let customs = self.customs.as_ref().expect("No custom opcodes");
+        customs[&code].handler.clone()
+    }
+}
+
+impl<'a, W: Word> Operands<'a, W> {
+    pub(crate) fn new(
+        vm: &'a mut VM<W>,
+        ip: usize,
+        instr: &'a Decoded<W>,
+    ) -> Self {
+        Operands { vm, ip, instr }
+    }
+
+    /// Address of the instruction being executed.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// The value of parameter `i`, according to its mode.
+    pub fn get(&self, i: usize) -> Result<W, Error> {
+        assert!(i < self.instr.opcode.arity(), "No parameter {}", i);
+        self.vm.get_value(self.ip, self.instr, i)
+    }
+
+    /// Writes to the address parameter `i` refers to.
+    pub fn set(&mut self, i: usize, value: W) -> Result<(), Error> {
+        assert!(i < self.instr.opcode.arity(), "No parameter {}", i);
+        let address = self.vm.get_address(self.ip, self.instr, i)?;
+        self.vm.assign_expand(self.ip, address, value)
+    }
+
+    /// Continues at `ip` instead of the next instruction.
+    pub fn jump(&mut self, ip: usize) {
+        self.vm.ip = ip;
+    }
+
+    pub fn vm(&mut self) -> &mut VM<W> {
+        self.vm
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{io, Error, Event, ExitReason, Value, VM};
+
+    #[test_log::new]
+    fn custom_opcodes() -> aoc::Result<()> {
+        let mut vm = VM::new();
+        // max a, b -> c
+        vm.register_opcode(20, 3, |ops| {
+            let max = ops.get(0)?.max(ops.get(1)?);
+            ops.set(2, max)?;
+            Ok(None)
+        })?;
+        // Prints its parameter, which shows up as output.
+        vm.register_opcode(21, 1, |ops| Ok(Some(Event::Output(ops.get(0)?))))?;
+        // Stops with the code in its parameter.
+        vm.register_opcode(22, 1, |ops| {
+            Err(Error::Trap { ip: ops.ip(), code: ops.get(0)? })
+        })?;
+        assert!(vm.register_opcode(1, 0, |_| Ok(None)).is_err());
+        assert!(vm.register_opcode(120, 0, |_| Ok(None)).is_err());
+
+        let program = [1120, -4, 3, 11, 4, 11, 121, 7, 1122, 99, 0, 0];
+        vm.read_mem(&program);
+        let mut output = Vec::new();
+        let exit = vm.run_with(io::Iter(None.into_iter()), &mut output);
+        assert_eq!(output, [3, 7]);
+        let trap = Error::Trap { ip: 8, code: 99 };
+        assert_eq!(exit.reason, ExitReason::Faulted(trap));
+
+        // Built-in opcodes are unaffected, and unregistered ones still fault.
+        let mut clone = vm.clone();
+        clone.read_mem(&[1101, 2, 3, 5, 104, 0, 23]);
+        assert_eq!(clone.resume()?, Event::Output(5));
+        assert_eq!(
+            clone.resume(),
+            Err(Error::UnknownOpcode { ip: 6, value: 23 })
+        );
+        let plain: &[Value] = &[1120, 1, 2, 0];
+        assert!(VM::with_mem(plain).resume().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,552 @@
+use crate::{
+    analysis::{Block, Cfg, Exit},
+    Line, Mode, Opcode, Operand, Value,
+};
+use std::{collections::BTreeSet, convert::TryFrom, fmt::Write};
+
+/// Turns a program into C-like pseudo-code. Memory cells become variables
+/// named after their address (`m100`), cells relative to the base are written
+/// `rb[2]`, and every detected subroutine becomes its own function. Control
+/// flow that does not fit an `if` or loop falls back to `goto`. Comments at
+/// the top point out where the program changes or may change its own code,
+/// where it runs into words that do not decode, and code that is only reached
+/// by jumping into the middle of other instructions. Such code follows the
+/// code it overlaps, which jumps over it.
+pub fn decompile(mem: &[Value]) -> String {
+    let cfg = Cfg::new(mem);
+    let mut entries = vec![0];
+    entries.extend(cfg.subroutines().into_iter().filter(|&e| e != 0));
+
+    // The first pass finds out which blocks are jumped to with `goto`, the
+    // second one prints them with labels.
+    let mut labels = BTreeSet::new();
+    loop {
+        let mut w = Writer {
+            cfg: &cfg,
+            out: String::new(),
+            labels,
+            gotos: BTreeSet::new(),
+            shown: BTreeSet::new(),
+            open: true,
+        };
+        for (i, &entry) in entries.iter().enumerate() {
+            let end = entries.get(i + 1).copied().unwrap_or(usize::MAX);
+            w.function(entry, end);
+        }
+        if w.gotos == w.labels {
+            return notes(&cfg) + &w.out;
+        }
+        labels = w.gotos;
+    }
+}
+
+/// Warnings about what the pseudo-code does not show.
+fn notes(cfg: &Cfg) -> String {
+    let mut out = String::new();
+    for write in &cfg.code_writes {
+        let _ = writeln!(
+            out,
+            "// note: the instruction at {} writes into the code at {}",
+            write.address, write.target
+        );
+    }
+    let n = cfg.relative_writes.len();
+    if n > 0 {
+        let s = if n == 1 { "" } else { "s" };
+        let _ = writeln!(
+            out,
+            "// note: {} write{} relative to rb may change the code too",
+            n, s
+        );
+    }
+    let mut end = 0;
+    for block in cfg.blocks.values() {
+        if block.start < end {
+            let _ = writeln!(
+                out,
+                "// note: the code at {} overlaps other instructions",
+                block.start
+            );
+        }
+        end = end.max(block.end);
+    }
+    for address in &cfg.invalid {
+        let _ =
+            writeln!(out, "// note: the word at {} does not decode", address);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+struct Writer<'a> {
+    cfg: &'a Cfg,
+    out: String,
+    labels: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+    /// Blocks that were emitted.
+    shown: BTreeSet<usize>,
+    /// Whether control can run off the end of what was emitted last.
+    open: bool,
+}
+
+/// The condition under which a `jt`/`jf` jumps.
+struct Cond {
+    value: String,
+    if_zero: bool,
+}
+
+impl Cond {
+    fn negate(self) -> Self {
+        Cond { if_zero: !self.if_zero, ..self }
+    }
+}
+
+impl std::fmt::Display for Cond {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.if_zero {
+            write!(f, "!{}", self.value)
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+fn name(entry: usize) -> String {
+    if entry == 0 {
+        "main".to_owned()
+    } else {
+        format!("sub_{}", entry)
+    }
+}
+
+fn operand(op: &Operand) -> String {
+    match op.mode {
+        Mode::Positional => format!("m{}", op.value),
+        Mode::Immediate => op.value.to_string(),
+        Mode::Relative => format!("rb[{}]", op.value),
+    }
+}
+
+fn operands(line: &Line) -> (Opcode, &[Operand]) {
+    match line {
+        Line::Instruction { opcode, operands, .. } => (*opcode, operands),
+        Line::Data { .. } => unreachable!("blocks only contain instructions"),
+    }
+}
+
+fn is_const(op: &Operand, x: Value) -> bool {
+    op.mode == Mode::Immediate && op.value == x
+}
+
+/// Renders a single non-jump instruction as a statement.
+fn statement(line: &Line) -> Option<String> {
+    use Opcode::*;
+    let (opcode, ops) = operands(line);
+    let [a, b, t] = match ops {
+        [a, b, t] => [operand(a), operand(b), operand(t)],
+        [a] => {
+            if opcode == Set && a.mode == Mode::Immediate && a.value < 0 {
+                return Some(format!("rb -= {};", -a.value));
+            }
+            let a = operand(a);
+            return Some(match opcode {
+                Read => format!("{} = input();", a),
+                Write => format!("output({});", a),
+                Set => format!("rb += {};", a),
+                _ => unreachable!(),
+            });
+        },
+        // Jumps that are never taken.
+        _ => return None,
+    };
+    let s = match opcode {
+        Add if is_const(&ops[1], 0) => format!("{} = {};", t, a),
+        Add if is_const(&ops[0], 0) => format!("{} = {};", t, b),
+        Add if a == t && ops[1].mode == Mode::Immediate && ops[1].value < 0 =>
+            format!("{} -= {};", t, -ops[1].value),
+        Add if a == t => format!("{} += {};", t, b),
+        Add if ops[1].mode == Mode::Immediate && ops[1].value < 0 =>
+            format!("{} = {} - {};", t, a, -ops[1].value),
+        Add => format!("{} = {} + {};", t, a, b),
+        Mul if is_const(&ops[1], 1) => format!("{} = {};", t, a),
+        Mul if is_const(&ops[0], 1) => format!("{} = {};", t, b),
+        Mul if is_const(&ops[1], -1) => format!("{} = -{};", t, a),
+        Mul if a == t => format!("{} *= {};", t, b),
+        Mul => format!("{} = {} * {};", t, a, b),
+        Lt => format!("{} = {} < {};", t, a, b),
+        Eq => format!("{} = {} == {};", t, a, b),
+        _ => unreachable!(),
+    };
+    Some(s)
+}
+
+impl Writer<'_> {
+    fn function(&mut self, entry: usize, end: usize) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        let _ = writeln!(self.out, "fn {}() {{", name(entry));
+        self.region(entry, end, 1);
+        let _ = writeln!(self.out, "}}");
+    }
+
+    fn line(&mut self, depth: usize, s: &str) {
+        let _ = writeln!(self.out, "{:1$}{2}", "", depth * 4, s);
+    }
+
+    /// Emits the blocks starting in `start..end`.
+    fn region(&mut self, start: usize, end: usize, depth: usize) {
+        let mut at = start;
+        while let Some((&next, _)) = self.cfg.blocks.range(at..end).next() {
+            at = match self.back_edge(next, end) {
+                Some(latch) => self.looped(next, latch, depth),
+                None => self.block(next, end, depth),
+            };
+            self.overlapping(next, at, depth);
+        }
+    }
+
+    /// Emits the blocks starting in `start..resume` that were left out
+    /// because they overlap the code before them. They are only reached by
+    /// jumping into them, so control that runs into them jumps over them.
+    fn overlapping(&mut self, start: usize, resume: usize, depth: usize) {
+        let skipped = self
+            .cfg
+            .blocks
+            .range(start + 1..resume)
+            .map(|(&start, _)| start)
+            .filter(|start| !self.shown.contains(start))
+            .collect::<Vec<_>>();
+        if skipped.is_empty() {
+            return;
+        }
+        if self.open {
+            self.goto(depth, None, resume);
+        }
+        for start in skipped {
+            let block = &self.cfg.blocks[&start];
+            self.body(block, depth);
+            self.exit(block, depth);
+            if self.open {
+                self.goto(depth, None, block.end);
+            }
+        }
+        self.open = false;
+    }
+
+    /// The last block in `header..end` that jumps back to `header`.
+    fn back_edge(&self, header: usize, end: usize) -> Option<usize> {
+        self.cfg
+            .blocks
+            .range(header..end)
+            .rev()
+            .find(|(_, b)| {
+                matches!(b.exit, Exit::Branch | Exit::Jump)
+                    && self.target(b) == Some(header)
+            })
+            .map(|(&start, _)| start)
+    }
+
+    fn looped(&mut self, header: usize, latch: usize, depth: usize) -> usize {
+        let block = &self.cfg.blocks[&latch];
+        let conditional = block.exit == Exit::Branch;
+        self.line(depth, if conditional { "do {" } else { "loop {" });
+        self.region(header, latch, depth + 1);
+        self.body(block, depth + 1);
+        if conditional {
+            let cond = self.cond(block);
+            self.line(depth, &format!("}} while ({});", cond));
+        } else {
+            self.line(depth, "}");
+        }
+        self.open = conditional;
+        block.end
+    }
+
+    /// Emits the block at `start`, along with any `if` it opens, and returns
+    /// where to continue.
+    fn block(&mut self, start: usize, end: usize, depth: usize) -> usize {
+        let block = &self.cfg.blocks[&start];
+        self.body(block, depth);
+        if block.exit == Exit::Branch {
+            let target = self.target(block).unwrap_or(usize::MAX);
+            if target > block.end && target <= end {
+                return self.conditional(block, target, end, depth);
+            }
+        }
+        self.exit(block, depth);
+        block.end
+    }
+
+    /// Emits how `block` ends, as a jump or call rather than an `if` or loop.
+    fn exit(&mut self, block: &Block, depth: usize) {
+        let (_, ops) = operands(block.lines.last().unwrap());
+        self.open = !matches!(
+            block.exit,
+            Exit::Jump | Exit::Return | Exit::Halt | Exit::Invalid
+        );
+        match block.exit {
+            Exit::Branch => {
+                let target = self.target(block).unwrap_or(usize::MAX);
+                let cond = self.cond(block);
+                self.goto(depth, Some(cond), target);
+            },
+            Exit::Jump => {
+                let target = self.target(block).unwrap_or(usize::MAX);
+                if target != block.end {
+                    self.goto(depth, None, target);
+                } else {
+                    self.open = true;
+                }
+            },
+            Exit::Call => {
+                let target = self.target(block).unwrap_or(usize::MAX);
+                self.line(depth, &format!("{}();", name(target)));
+            },
+            Exit::Return => self.line(depth, "return;"),
+            Exit::Halt => self.line(depth, "halt();"),
+            Exit::Indirect => {
+                let s = format!("goto *{};", operand(&ops[ops.len() - 1]));
+                match ops {
+                    [c, _] if c.mode != Mode::Immediate => {
+                        let cond = self.cond(block);
+                        self.line(depth, &format!("if ({}) {}", cond, s));
+                    },
+                    _ => {
+                        self.line(depth, &s);
+                        self.open = false;
+                    },
+                }
+            },
+            Exit::Invalid => {
+                self.line(depth, &format!("invalid({});", block.end));
+            },
+            Exit::Next => {},
+        }
+    }
+
+    /// An `if`, or an `if`/`else` when the code skipped by the branch ends
+    /// by jumping over the code at `target`.
+    fn conditional(
+        &mut self,
+        block: &Block,
+        target: usize,
+        end: usize,
+        depth: usize,
+    ) -> usize {
+        let cond = self.cond(block).negate();
+        self.line(depth, &format!("if ({}) {{", cond));
+        let join = self
+            .cfg
+            .blocks
+            .range(block.end..target)
+            .next_back()
+            .filter(|(_, b)| b.exit == Exit::Jump)
+            .and_then(|(&start, b)| Some((start, self.target(b)?)))
+            .filter(|&(_, join)| join > target && join <= end);
+        match join {
+            Some((jump, join)) => {
+                self.region(block.end, jump, depth + 1);
+                self.body(&self.cfg.blocks[&jump], depth + 1);
+                self.line(depth, "} else {");
+                self.region(target, join, depth + 1);
+                self.line(depth, "}");
+                self.open = true;
+                join
+            },
+            None => {
+                self.region(block.end, target, depth + 1);
+                self.line(depth, "}");
+                self.open = true;
+                target
+            },
+        }
+    }
+
+    /// The label and statements of a block, leaving out its final jump and,
+    /// for calls, the push of the return address.
+    fn body(&mut self, block: &Block, depth: usize) {
+        self.shown.insert(block.start);
+        if self.labels.contains(&block.start) {
+            let label = format!("label_{}:", block.start);
+            self.line(depth.saturating_sub(1), &label);
+        }
+        let control = !matches!(block.exit, Exit::Next | Exit::Invalid);
+        let n = block.lines.len() - usize::from(control);
+        let site = block.lines.last().map(Line::address);
+        let call = match block.exit {
+            Exit::Call => self.cfg.calls.iter().find(|c| Some(c.site) == site),
+            _ => None,
+        };
+        for line in &block.lines[..n] {
+            if let Some(call) = call {
+                let (opcode, ops) = operands(line);
+                let pushed = match opcode {
+                    Opcode::Add => ops[0].value.checked_add(ops[1].value),
+                    Opcode::Mul => ops[0].value.checked_mul(ops[1].value),
+                    _ => None,
+                };
+                let is_push = ops.len() == 3
+                    && ops[..2].iter().all(|o| o.mode == Mode::Immediate)
+                    && ops[2].mode == Mode::Relative;
+                if is_push && pushed == Value::try_from(call.return_to).ok() {
+                    continue;
+                }
+            }
+            if let Some(s) = statement(line) {
+                self.line(depth, &s);
+            }
+        }
+    }
+
+    fn target(&self, block: &Block) -> Option<usize> {
+        let (_, ops) = operands(block.lines.last()?);
+        match ops {
+            [_, Operand { mode: Mode::Immediate, value }] =>
+                usize::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    fn cond(&self, block: &Block) -> Cond {
+        let (opcode, ops) = operands(block.lines.last().unwrap());
+        Cond { value: operand(&ops[0]), if_zero: opcode == Opcode::Jif }
+    }
+
+    /// A jump to `target`, or a fault if nothing decodes there.
+    fn goto(&mut self, depth: usize, cond: Option<Cond>, target: usize) {
+        let s = if self.cfg.invalid.binary_search(&target).is_ok() {
+            format!("invalid({});", target)
+        } else {
+            self.gotos.insert(target);
+            format!("goto label_{};", target)
+        };
+        let s = match cond {
+            Some(cond) => format!("if ({}) {}", cond, s),
+            None => s,
+        };
+        self.line(depth, &s);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    #[test_log::new]
+    fn structured() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+                    arb  #stack
+                    in   [n]
+                    add  #back, #0, rb+0
+                    jt   #1, #sum
+            back:   jf   [total], #zero
+                    out  [total]
+                    jt   #1, #done
+            zero:   out  #-1
+            done:   hlt
+            ; adds n, n - 1, ..., 1 to total
+            sum:    add  [total], [n], [total]
+                    add  [n], #-1, [n]
+                    jt   [n], #sum
+                    jt   #1, rb+0
+            n:      db   0
+            total:  db   0
+            stack:  db   0
+            "#,
+        )?;
+
+        assert_eq!(
+            decompile(&program),
+            concat!(
+                "// note: 1 write relative to rb may change the code too\n",
+                "\n",
+                "fn main() {\n",
+                "    rb += 38;\n",
+                "    m36 = input();\n",
+                "    sub_22();\n",
+                "    if (m37) {\n",
+                "        output(m37);\n",
+                "    } else {\n",
+                "        output(-1);\n",
+                "    }\n",
+                "    halt();\n",
+                "}\n",
+                "\n",
+                "fn sub_22() {\n",
+                "    do {\n",
+                "        m37 += m36;\n",
+                "        m36 -= 1;\n",
+                "    } while (m36);\n",
+                "    return;\n",
+                "}\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn notes() -> aoc::Result<()> {
+        // Patches the opcode at 6 before running it.
+        const DIAGNOSTIC: &str = include_str!("../../day05/input.txt");
+        let vm = crate::VM::with_program(DIAGNOSTIC)?;
+        assert_eq!(
+            decompile(vm.mem()),
+            concat!(
+                "// note: the instruction at 2 writes into the code at 6\n",
+                "// note: the word at 6 does not decode\n",
+                "\n",
+                "fn main() {\n",
+                "    m225 = input();\n",
+                "    m6 = m225 + m6;\n",
+                "    invalid(6);\n",
+                "}\n",
+            )
+        );
+
+        assert_eq!(
+            decompile(&[1101, 0, 99, 20, 1105, 1, 2]),
+            concat!(
+                "// note: the code at 2 overlaps other instructions\n",
+                "\n",
+                "fn main() {\n",
+                "    m20 = 99;\n",
+                "    goto label_4;\n",
+                "label_2:\n",
+                "    halt();\n",
+                "label_4:\n",
+                "    goto label_2;\n",
+                "}\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn puzzle_inputs() -> aoc::Result<()> {
+        let inputs = [
+            include_str!("../../day11/input.txt"),
+            include_str!("../../day13/input.txt"),
+            include_str!("../../day15/input.txt"),
+            include_str!("../../day17/input.txt"),
+        ];
+        for input in inputs {
+            let vm = crate::VM::with_program(input)?;
+            let out = decompile(vm.mem());
+            assert!(
+                out.starts_with("// note") && out.contains("fn main() {\n")
+            );
+            // Every jump goes to exactly one label.
+            for (i, _) in out.match_indices("goto label_") {
+                let label = &out[i + 5..];
+                let label = format!("{}:", &label[..label.find(';').unwrap()]);
+                assert_eq!(out.matches(&label).count(), 1, "{}", label);
+            }
+        }
+        Ok(())
+    }
+}
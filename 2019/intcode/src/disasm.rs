@@ -0,0 +1,136 @@
+use crate::{decode, Mode, Opcode, Value};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Line {
+    Instruction { address: usize, opcode: Opcode, operands: Vec<Operand> },
+    Data { address: usize, value: Value },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match *self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } =>
+                address,
+        }
+    }
+
+    /// Number of memory words covered by this line.
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Positional => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            Mode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instruction { address, opcode, operands } => {
+                if operands.is_empty() {
+                    return write!(f, "{:>6}: {}", address, opcode.mnemonic());
+                }
+
+                write!(f, "{:>6}: {:<4}", address, opcode.mnemonic())?;
+                for (i, op) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", op)?;
+                }
+                Ok(())
+            },
+            Line::Data { address, value } =>
+                write!(f, "{:>6}: {:<4}{}", address, "db", value),
+        }
+    }
+}
+
+/// Decodes the instruction starting at `address`, falling back to a data
+/// word if it is not a well-formed instruction.
+pub fn decode_at(mem: &[Value], address: usize) -> Line {
+    let word = mem[address];
+    let data = Line::Data { address, value: word };
+    let (opcode, modes) = match decode(word) {
+        Some(decoded) => decoded,
+        None => return data,
+    };
+
+    let arity = opcode.arity();
+    let unused_modes = word / 10i64.pow(2 + arity as u32);
+    let params = match mem.get(address + 1..address + 1 + arity) {
+        Some(params) if unused_modes == 0 => params,
+        _ => return data,
+    };
+    if let Some(t) = opcode.target() {
+        if modes[t] == Mode::Immediate {
+            return data;
+        }
+    }
+
+    let operands = params
+        .iter()
+        .zip(modes.iter())
+        .map(|(&value, &mode)| Operand { mode, value })
+        .collect();
+    Line::Instruction { address, opcode, operands }
+}
+
+/// Linear sweep over `mem`, decoding every word that starts a valid
+/// instruction and treating everything else as data.
+pub fn disassemble(mem: &[Value]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < mem.len() {
+        let line = decode_at(mem, address);
+        address += line.size();
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::new]
+    fn listing() -> aoc::Result<()> {
+        let mem = [109, 1, 204, -1, 1001, 100, 1, 100, 11108, 99, -7, 1, 0];
+        let listing = disassemble(&mem)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listing,
+            [
+                "     0: arb #1",
+                "     2: out rb-1",
+                "     4: add [100], #1, [100]",
+                "     8: db  11108",
+                "     9: hlt",
+                "    10: db  -7",
+                "    11: db  1",
+                "    12: db  0",
+            ]
+        );
+        Ok(())
+    }
+}
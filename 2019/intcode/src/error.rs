@@ -0,0 +1,107 @@
+use crate::Value;
+use std::fmt;
+
+/// A fault raised while executing an intcode program. Every variant
+/// carries the address of the instruction that caused it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    UnknownOpcode {
+        ip: usize,
+        value: Value,
+    },
+    InvalidMode {
+        ip: usize,
+        value: Value,
+    },
+    ImmediateWrite {
+        ip: usize,
+    },
+    NegativeAddress {
+        ip: usize,
+        address: Value,
+    },
+    Overflow {
+        ip: usize,
+    },
+    IpOutOfBounds {
+        ip: usize,
+    },
+    MemoryLimit {
+        ip: usize,
+        address: usize,
+    },
+    NotConnected {
+        ip: usize,
+    },
+    BudgetExhausted {
+        ip: usize,
+        executed: u64,
+    },
+    DeadlineExceeded {
+        ip: usize,
+        executed: u64,
+    },
+    /// Raised by custom instructions that want to stop the machine.
+    Trap {
+        ip: usize,
+        code: Value,
+    },
+}
+
+impl Error {
+    pub fn ip(&self) -> usize {
+        use Error::*;
+        match *self {
+            UnknownOpcode { ip, .. }
+            | InvalidMode { ip, .. }
+            | ImmediateWrite { ip }
+            | NegativeAddress { ip, .. }
+            | Overflow { ip }
+            | IpOutOfBounds { ip }
+            | MemoryLimit { ip, .. }
+            | NotConnected { ip }
+            | BudgetExhausted { ip, .. }
+            | DeadlineExceeded { ip, .. }
+            | Trap { ip, .. } => ip,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+        match *self {
+            UnknownOpcode { ip, value } =>
+                write!(f, "Unknown opcode {} at ip {}", value, ip),
+            InvalidMode { ip, value } =>
+                write!(f, "Invalid parameter mode in {} at ip {}", value, ip),
+            ImmediateWrite { ip } =>
+                write!(f, "Write to immediate mode parameter at ip {}", ip),
+            NegativeAddress { ip, address } =>
+                write!(f, "Negative address {} at ip {}", address, ip),
+            Overflow { ip } => write!(f, "Arithmetic overflow at ip {}", ip),
+            IpOutOfBounds { ip } =>
+                write!(f, "Instruction at ip {} runs past end of memory", ip),
+            MemoryLimit { ip, address } => write!(
+                f,
+                "Write to [{}] at ip {} exceeds the memory limit",
+                address, ip
+            ),
+            NotConnected { ip } =>
+                write!(f, "No IO channels connected at ip {}", ip),
+            BudgetExhausted { ip, executed } => write!(
+                f,
+                "Instruction budget exhausted after {} instructions at ip {}",
+                executed, ip
+            ),
+            DeadlineExceeded { ip, executed } => write!(
+                f,
+                "Deadline exceeded after {} instructions at ip {}",
+                executed, ip
+            ),
+            Trap { ip, code } => write!(f, "Trap {} at ip {}", code, ip),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,126 @@
+//! Input and output devices that `VM::run_with` can be attached to.
+
+use crate::{Signal, Value};
+use std::{collections::VecDeque, sync::mpsc};
+
+pub trait Input<W = Value> {
+    /// The next value for a `Read` instruction, or `None` once the input is
+    /// exhausted, which stops the machine.
+    fn read(&mut self) -> Option<W>;
+}
+
+pub trait Output<W = Value> {
+    fn write(&mut self, value: W);
+
+    /// Called once when the machine stops.
+    fn close(&mut self) {}
+}
+
+/// Input taken from an iterator.
+pub struct Iter<I>(pub I);
+
+/// Input produced or output consumed by a closure.
+pub struct Func<F>(pub F);
+
+/// Wraps a device and records every value that passes through it.
+#[derive(Clone, Debug, Default)]
+pub struct Tape<D, W = Value> {
+    pub device: D,
+    pub values: Vec<W>,
+}
+
+impl<D, W> Tape<D, W> {
+    pub fn new(device: D) -> Self {
+        Tape { device, values: Vec::new() }
+    }
+}
+
+impl<W, T: Input<W> + ?Sized> Input<W> for &mut T {
+    fn read(&mut self) -> Option<W> {
+        (**self).read()
+    }
+}
+
+impl<W, T: Output<W> + ?Sized> Output<W> for &mut T {
+    fn write(&mut self, value: W) {
+        (**self).write(value)
+    }
+
+    fn close(&mut self) {
+        (**self).close()
+    }
+}
+
+impl Input for mpsc::Receiver<Signal> {
+    fn read(&mut self) -> Option<Value> {
+        match self.recv() {
+            Ok(Signal::Value(x)) => Some(x),
+            Ok(Signal::Halting) | Err(_) => None,
+        }
+    }
+}
+
+impl Output for mpsc::Sender<Signal> {
+    fn write(&mut self, value: Value) {
+        let _ = self.send(Signal::Value(value));
+    }
+
+    fn close(&mut self) {
+        let _ = self.send(Signal::Halting);
+    }
+}
+
+impl<W> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value);
+    }
+}
+
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value);
+    }
+}
+
+impl<W, I: Iterator<Item = W>> Input<W> for Iter<I> {
+    fn read(&mut self) -> Option<W> {
+        self.0.next()
+    }
+}
+
+impl<W, F: FnMut() -> Option<W>> Input<W> for Func<F> {
+    fn read(&mut self) -> Option<W> {
+        (self.0)()
+    }
+}
+
+impl<W, F: FnMut(W)> Output<W> for Func<F> {
+    fn write(&mut self, value: W) {
+        (self.0)(value)
+    }
+}
+
+impl<W: Clone, D: Input<W>> Input<W> for Tape<D, W> {
+    fn read(&mut self) -> Option<W> {
+        let value = self.device.read()?;
+        self.values.push(value.clone());
+        Some(value)
+    }
+}
+
+impl<W: Clone, D: Output<W>> Output<W> for Tape<D, W> {
+    fn write(&mut self, value: W) {
+        self.values.push(value.clone());
+        self.device.write(value);
+    }
+
+    fn close(&mut self) {
+        self.device.close();
+    }
+}
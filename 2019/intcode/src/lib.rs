@@ -1,25 +1,123 @@
-use std::{convert::TryFrom, sync::mpsc};
+pub mod analysis;
+pub mod ascii;
+pub mod io;
+pub mod network;
+
+mod asm;
+mod cache;
+mod custom;
+mod decompile;
+mod disasm;
+mod error;
+mod memory;
+mod pool;
+mod profile;
+mod snapshot;
+mod task;
+mod transcript;
+mod undo;
+mod word;
+
+pub use asm::assemble;
+pub use custom::Operands;
+pub use decompile::decompile;
+pub use disasm::{decode_at, disassemble, Line, Operand};
+pub use error::Error;
+pub use memory::{LimitExceeded, Memory, MemoryStats, PAGE_SIZE};
+pub use pool::{Pool, Pooled, ProgramImage};
+pub use profile::{Branch, Loop, Profile};
+pub use snapshot::Snapshot;
+pub use transcript::{Entry, Transcript};
+pub use word::Word;
+
+use cache::{Cache, Decoded};
+use custom::Extensions;
+use std::{
+    collections::VecDeque, convert::TryFrom, str::FromStr, sync::mpsc,
+    time::Instant,
+};
+use undo::UndoLog;
 
 pub type Value = i64;
 
+/// How many instructions run between two looks at the clock.
+const DEADLINE_INTERVAL: u64 = 1024;
+
+/// How many instructions a machine runs before it starts filling the decode
+/// cache by itself. Short runs spend more on filling it than they save.
+const LAZY_CACHE_AFTER: u64 = 4096;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Signal {
     Value(Value),
     Halting,
 }
 
+/// What executing one instruction did besides changing the machine.
+#[derive(Default)]
+struct Effect<W> {
+    event: Option<Event<W>>,
+    /// Whether a conditional jump was taken.
+    taken: Option<bool>,
+    /// The input a `Read` consumed, kept for the undo log.
+    consumed: Option<W>,
+}
+
+/// The reason `VM::resume` handed control back to its caller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event<W = Value> {
+    NeedsInput,
+    Output(W),
+    Halted,
+}
+
+/// Why a run ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExitReason {
+    /// The program executed `Halt`.
+    Halted,
+    /// The program asked for input after the input had ended.
+    InputClosed,
+    /// Nobody is listening to the output anymore.
+    OutputClosed,
+    BudgetExhausted,
+    DeadlineExceeded,
+    Faulted(Error),
+}
+
+/// How a run ended, with the ip it ended at and the total number of
+/// instructions executed. After a fault, `ip` is the address of the
+/// faulting instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Exit {
+    pub reason: ExitReason,
+    pub ip: usize,
+    pub executed: u64,
+}
+
+/// An intcode machine. Memory cells are `Value`s by default; any other
+/// `Word` type can be used for programs that need wider arithmetic, but only
+/// `VM<Value>` can be connected to channels or snapshotted.
 #[derive(Default)]
-pub struct VM {
+pub struct VM<W = Value> {
     ip: usize,
     rp: Value,
-    instr: Instruction,
+    pending: VecDeque<W>,
     input: Option<mpsc::Receiver<Signal>>,
     output: Option<mpsc::Sender<Signal>>,
-    mem: Vec<Value>,
+    mem: Memory<W>,
+    cache: Cache<W>,
+    custom: Extensions<W>,
+    profile: Option<Box<Profile>>,
+    transcript: Option<Transcript<W>>,
+    undo: Option<UndoLog<W>>,
+    executed: u64,
+    budget: Option<u64>,
+    deadline: Option<Instant>,
 }
 
-#[derive(Clone, Copy)]
-enum Opcode {
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Opcode {
     Add,
     Mul,
     Read,
@@ -30,19 +128,41 @@ enum Opcode {
     Eq,
     Set,
     Halt,
+    /// An instruction registered with `VM::register_opcode`.
+    Custom {
+        code: u8,
+        arity: u8,
+    },
 }
 
-#[derive(Clone, Copy)]
-enum Mode {
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
     Positional,
     Immediate,
     Relative,
 }
 
-#[derive(Default)]
-struct Instruction {
-    mp: usize,
-    modes: [Mode; 3],
+/// Clones the execution state of a machine. The copy has no IO channels
+/// connected, so it can be driven independently with `resume`.
+impl<W: Word> Clone for VM<W> {
+    fn clone(&self) -> Self {
+        VM {
+            ip: self.ip,
+            rp: self.rp,
+            pending: self.pending.clone(),
+            input: None,
+            output: None,
+            mem: self.mem.clone(),
+            cache: self.cache.clone(),
+            custom: self.custom.clone(),
+            profile: self.profile.clone(),
+            transcript: self.transcript.clone(),
+            undo: self.undo.clone(),
+            executed: self.executed,
+            budget: self.budget,
+            deadline: self.deadline,
+        }
+    }
 }
 
 impl TryFrom<Value> for Opcode {
@@ -68,6 +188,80 @@ impl TryFrom<Value> for Opcode {
     }
 }
 
+impl Opcode {
+    pub fn mnemonic(self) -> &'static str {
+        use Opcode::*;
+        match self {
+            Add => "add",
+            Mul => "mul",
+            Read => "in",
+            Write => "out",
+            Jit => "jt",
+            Jif => "jf",
+            Lt => "lt",
+            Eq => "eq",
+            Set => "arb",
+            Halt => "hlt",
+            Custom { .. } => "custom",
+        }
+    }
+
+    /// Number of parameters following the instruction word.
+    #[inline]
+    pub fn arity(self) -> usize {
+        use Opcode::*;
+        match self {
+            Add | Mul | Lt | Eq => 3,
+            Jit | Jif => 2,
+            Read | Write | Set => 1,
+            Halt => 0,
+            Custom { arity, .. } => arity as usize,
+        }
+    }
+
+    /// Index of the parameter the instruction writes its result to, if any.
+    pub fn target(self) -> Option<usize> {
+        use Opcode::*;
+        match self {
+            Add | Mul | Lt | Eq => Some(2),
+            Read => Some(0),
+            Write | Jit | Jif | Set | Halt | Custom { .. } => None,
+        }
+    }
+}
+
+impl From<Opcode> for Value {
+    fn from(opcode: Opcode) -> Self {
+        use Opcode::*;
+        match opcode {
+            Add => 1,
+            Mul => 2,
+            Read => 3,
+            Write => 4,
+            Jit => 5,
+            Jif => 6,
+            Lt => 7,
+            Eq => 8,
+            Set => 9,
+            Halt => 99,
+            Custom { code, .. } => code.into(),
+        }
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Opcode::*;
+        [Add, Mul, Read, Write, Jit, Jif, Lt, Eq, Set, Halt]
+            .iter()
+            .copied()
+            .find(|op| op.mnemonic() == s)
+            .ok_or_else(|| aoc::format_err!("Unknown mnemonic: {}", s))
+    }
+}
+
 impl Default for Mode {
     fn default() -> Self {
         Mode::Positional
@@ -90,6 +284,16 @@ impl TryFrom<Value> for Mode {
     }
 }
 
+impl From<Mode> for Value {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Positional => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
 impl VM {
     pub fn new() -> Self {
         VM::default()
@@ -107,30 +311,33 @@ impl VM {
         vm
     }
 
-    pub fn read_program(&mut self, program: &str) -> aoc::Result<()> {
-        self.ip = 0;
-        self.rp = 0;
-        self.mem.clear();
-        for num in program.trim().split(',') {
-            let x = num.parse()?;
-            self.mem.push(x);
+    /// Captures the execution state, leaving out the IO channels.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            rp: self.rp,
+            pending: self.pending.iter().copied().collect(),
+            mem: self.mem.dense().to_vec(),
+            sparse: self.mem.sparse(),
         }
-        Ok(())
-    }
-
-    pub fn read_mem(&mut self, mem: &[Value]) {
-        self.ip = 0;
-        self.rp = 0;
-        self.mem.resize(mem.len(), 0);
-        self.mem.copy_from_slice(mem);
-    }
-
-    pub fn mem(&self) -> &[Value] {
-        &self.mem
     }
 
-    pub fn mem_mut(&mut self) -> &mut [Value] {
-        &mut self.mem
+    /// Puts the machine into the state captured by `snapshot`. Fails if
+    /// its sparse cells do not fit in the memory limit, leaving the machine
+    /// partly restored.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.ip = snapshot.ip;
+        self.rp = snapshot.rp;
+        self.pending = snapshot.pending.iter().copied().collect();
+        self.mem.load(&snapshot.mem);
+        self.cache.clear();
+        self.clear_undo();
+        for &(address, value) in &snapshot.sparse {
+            self.mem
+                .set(address, value)
+                .map_err(|_| Error::MemoryLimit { ip: snapshot.ip, address })?;
+        }
+        Ok(())
     }
 
     pub fn connect_io(
@@ -152,160 +359,556 @@ impl VM {
         (tx, rx)
     }
 
-    pub fn run(&mut self) -> aoc::Result<()> {
+    pub fn run(&mut self) -> Exit {
+        let ip = self.ip;
+        match (self.input.take(), self.output.take()) {
+            (Some(input), Some(output)) => self.run_with(input, output),
+            _ => self.exit(ExitReason::Faulted(Error::NotConnected { ip })),
+        }
+    }
+
+    /// Runs the machine on the rayon thread pool. Errors are only logged;
+    /// use `into_task` to find out how the run ended.
+    pub fn spawn(mut self) -> (mpsc::Sender<Signal>, mpsc::Receiver<Signal>) {
+        let ends = self.setup_io();
+        rayon::spawn(move || {
+            // The output is closed either way, so the other end notices.
+            if let ExitReason::Faulted(e) = self.run().reason {
+                log::error!("{}", e);
+            }
+        });
+        ends
+    }
+}
+
+impl<W: Word> VM<W> {
+    pub fn read_program(&mut self, program: &str) -> aoc::Result<()>
+    where
+        W::Err: std::error::Error + 'static,
+    {
+        self.ip = 0;
+        self.rp = 0;
+        self.pending.clear();
+        let mem = program
+            .trim()
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<W>, _>>()?;
+        self.mem.load(&mem);
+        self.cache.clear();
+        self.clear_undo();
+        Ok(())
+    }
+
+    pub fn read_mem(&mut self, mem: &[W]) {
+        self.ip = 0;
+        self.rp = 0;
+        self.pending.clear();
+        self.mem.load(mem);
+        self.cache.clear();
+        self.clear_undo();
+    }
+
+    /// The dense part of memory, which starts with the program. Cells in
+    /// far away sparse pages are only reachable through `peek`.
+    pub fn mem(&self) -> &[W] {
+        self.mem.dense()
+    }
+
+    /// Gives direct access to memory, dropping all decoded instructions
+    /// since changes made through it cannot be tracked.
+    pub fn mem_mut(&mut self) -> &mut [W] {
+        self.cache.clear();
+        self.mem.dense_mut()
+    }
+
+    /// Decodes every instruction in memory up front. Clones made afterwards
+    /// share the decoded instructions, which pays off when the same program
+    /// is run many times.
+    pub fn precompile(&mut self) {
+        self.cache.compile(&self.mem, &self.custom);
+    }
+
+    /// Turns caching of decoded instructions on or off. It is on by default,
+    /// but only kicks in after a few thousand instructions unless the
+    /// machine was precompiled.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache.set_enabled(enabled);
+    }
+
+    /// Adds an instruction with opcode `code` and up to three parameters,
+    /// whose modes are decoded like those of the built-in instructions.
+    /// Built-in opcodes cannot be replaced. Like the built-in instructions,
+    /// a handler returning `NeedsInput` or `Halted` leaves `ip` in place.
+    pub fn register_opcode<F>(
+        &mut self,
+        code: Value,
+        arity: usize,
+        handler: F,
+    ) -> aoc::Result<()>
+    where
+        F: Fn(&mut Operands<'_, W>) -> Result<Option<Event<W>>, Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.custom.register(code, arity, std::sync::Arc::new(handler))?;
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Caps the number of memory words the program may allocate.
+    pub fn set_memory_limit(&mut self, words: Option<usize>) {
+        self.mem.set_limit(words);
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.mem.stats()
+    }
+
+    /// Caps the total number of instructions the machine may execute. Once
+    /// it is used up, execution stops with `Error::BudgetExhausted` until the
+    /// budget is raised.
+    pub fn set_instruction_budget(&mut self, instructions: Option<u64>) {
+        self.budget = instructions;
+    }
+
+    /// Stops execution with `Error::DeadlineExceeded` once `deadline` has
+    /// passed. The clock is only checked every so many instructions.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Number of instructions executed so far. Blocked reads and halts do
+    /// not count.
+    pub fn instructions_executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
+
+    pub fn rp(&self) -> Value {
+        self.rp
+    }
+
+    pub fn set_rp(&mut self, rp: Value) {
+        self.rp = rp;
+    }
+
+    /// Reads a memory cell, treating addresses past the end as zero.
+    pub fn peek(&self, address: usize) -> W {
+        self.mem.get(address)
+    }
+
+    /// Writes a memory cell, growing memory if needed.
+    pub fn poke(&mut self, address: usize, value: W) -> Result<(), Error> {
+        self.assign(self.ip, address, value)
+    }
+
+    pub fn pending_input(&self) -> &VecDeque<W> {
+        &self.pending
+    }
+
+    /// Starts counting executions per address and opcode, discarding any
+    /// previously collected profile.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Box::default());
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take().map(|p| *p)
+    }
+
+    /// Starts recording every value read and written, discarding any
+    /// previous transcript.
+    pub fn enable_recording(&mut self) {
+        self.transcript = Some(Transcript::default());
+    }
+
+    pub fn transcript(&self) -> Option<&Transcript<W>> {
+        self.transcript.as_ref()
+    }
+
+    pub fn take_transcript(&mut self) -> Option<Transcript<W>> {
+        self.transcript.take()
+    }
+
+    /// Runs the program until it halts or `input` is exhausted, closing
+    /// `output` when done.
+    pub fn run_with(
+        &mut self,
+        mut input: impl io::Input<W>,
+        mut output: impl io::Output<W>,
+    ) -> Exit {
+        let reason = loop {
+            match self.resume() {
+                Ok(Event::NeedsInput) => match input.read() {
+                    Some(x) => self.provide_input(x),
+                    None => break ExitReason::InputClosed,
+                },
+                Ok(Event::Output(x)) => output.write(x),
+                Ok(Event::Halted) => break ExitReason::Halted,
+                Err(e) => break e.into(),
+            }
+        };
+
+        output.close();
+        self.exit(reason)
+    }
+
+    fn exit(&self, reason: ExitReason) -> Exit {
+        Exit { reason, ip: self.ip, executed: self.executed }
+    }
+
+    /// Queues a value for the next `Read` instruction.
+    pub fn provide_input(&mut self, value: W) {
+        self.pending.push_back(value);
+    }
+
+    /// Runs the program on the current thread until it produces output,
+    /// needs input that has not been provided yet, or halts.
+    pub fn resume(&mut self) -> Result<Event<W>, Error> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Executes a single instruction. A `Read` without pending input, a
+    /// `Halt` and a fault leave `ip` on the instruction so it can be
+    /// retried.
+    // The interpreter loop is generic, so it gets compiled in the crate that
+    // uses it; forcing it and its helpers inline keeps it as fast as it was
+    // before `Word` was introduced.
+    #[inline(always)]
+    pub fn step(&mut self) -> Result<Option<Event<W>>, Error> {
+        let start = self.ip;
+        let rp = self.rp;
+        let executed = self.executed;
+        if self.budget.is_some_and(|budget| executed >= budget) {
+            return Err(Error::BudgetExhausted { ip: start, executed });
+        }
+        if executed.is_multiple_of(DEADLINE_INTERVAL)
+            && self.deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            return Err(Error::DeadlineExceeded { ip: start, executed });
+        }
+        let instr = self.fetch(start)?;
+        let opcode = instr.opcode;
+        log::debug!(
+            "ip := {}\tinstr := {:05}\trelbase := {}",
+            start,
+            self.mem.get(start),
+            self.rp
+        );
+
+        self.ip = start + 1 + opcode.arity();
+        let effect = match self.execute(start, &instr) {
+            Ok(Effect {
+                event: Some(event @ (Event::NeedsInput | Event::Halted)),
+                ..
+            }) => {
+                self.ip = start;
+                return Ok(Some(event));
+            },
+            Ok(effect) => effect,
+            Err(e) => {
+                self.ip = start;
+                return Err(e);
+            },
+        };
+
+        self.executed += 1;
+        if let Some(undo) = &mut self.undo {
+            undo.commit(start, rp, effect.consumed);
+        }
+        let ip = self.ip;
+        if let Some(profile) = &mut self.profile {
+            profile.record(start, opcode, effect.taken.map(|t| (t, ip)));
+        }
+        Ok(effect.event)
+    }
+
+    /// Carries out the instruction at `start`, with `ip` already moved past
+    /// it. Pending input is only consumed once it has been stored.
+    #[inline(always)]
+    fn execute(
+        &mut self,
+        start: usize,
+        instr: &Decoded<W>,
+    ) -> Result<Effect<W>, Error> {
         use Opcode::*;
-        let input = self
-            .input
-            .take()
-            .ok_or_else(|| aoc::format_err!("No input channel connected"))?;
-        let output = self
-            .output
-            .take()
-            .ok_or_else(|| aoc::format_err!("No output channel connected"))?;
 
         macro_rules! store {
-            ($x:expr) => {
+            ($i:expr, $x:expr) => {
                 let value = $x;
-                let address = self.get_address()?;
-                self.assign_expand(address, value);
+                let address = self.get_address(start, instr, $i)?;
+                self.assign_expand(start, address, value)?;
             };
         }
 
-        loop {
-            log::debug!(
-                "ip := {}\tinstr := {:05}\trelbase := {}",
-                self.ip,
-                self.mem[self.ip],
-                self.rp
-            );
-            let opcode = self.get_opcode()?;
-            match opcode {
-                // 1
-                Add => {
-                    let result = self
-                        .get_value()?
-                        .checked_add(self.get_value()?)
-                        .unwrap();
-                    store!(result);
-                },
-                // 2
-                Mul => {
-                    let result = self
-                        .get_value()?
-                        .checked_mul(self.get_value()?)
-                        .unwrap();
-                    store!(result);
-                },
-                // 3
-                Read => {
-                    let value = match input.recv().unwrap() {
-                        Signal::Value(x) => x,
-                        Signal::Halting => break,
-                    };
-                    store!(value);
-                },
-                // 4
-                Write => {
-                    let _ = output.send(Signal::Value(self.get_value()?));
-                },
-                // 5, 6
-                Jit | Jif => {
-                    let val = self.get_value()?;
-                    let cond = match opcode {
-                        Jit => val != 0,
-                        Jif => val == 0,
-                        _ => unreachable!(),
-                    };
-                    let value = self.get_value()?;
-                    if cond {
-                        self.ip = usize::try_from(value)?;
-                    }
-                },
-                // 7, 8
-                Lt | Eq => {
-                    let a = self.get_value()?;
-                    let b = self.get_value()?;
-                    let cond = match opcode {
-                        Lt => a < b,
-                        Eq => a == b,
-                        _ => unreachable!(),
-                    };
-                    let address = self.get_address()?;
-                    if cond {
-                        self.assign_expand(address, 1);
-                    } else {
-                        self.assign_expand(address, 0);
-                    }
-                },
-                // 9
-                Set => self.rp += self.get_value()?,
-                // 99
-                Halt => break,
-            }
+        let opcode = instr.opcode;
+        let mut effect = Effect::default();
+        match opcode {
+            // 1
+            Add => {
+                let result = self
+                    .get_value(start, instr, 0)?
+                    .checked_add(&self.get_value(start, instr, 1)?)
+                    .ok_or(Error::Overflow { ip: start })?;
+                store!(2, result);
+            },
+            // 2
+            Mul => {
+                let result = self
+                    .get_value(start, instr, 0)?
+                    .checked_mul(&self.get_value(start, instr, 1)?)
+                    .ok_or(Error::Overflow { ip: start })?;
+                store!(2, result);
+            },
+            // 3
+            Read => {
+                let value = match self.pending.front() {
+                    Some(x) => x.clone(),
+                    None => {
+                        effect.event = Some(Event::NeedsInput);
+                        return Ok(effect);
+                    },
+                };
+                store!(0, value);
+                let value = self.pending.pop_front().unwrap();
+                self.record(Entry::Input, &value);
+                if self.undo.is_some() {
+                    effect.consumed = Some(value);
+                }
+            },
+            // 4
+            Write => {
+                let value = self.get_value(start, instr, 0)?;
+                self.record(Entry::Output, &value);
+                effect.event = Some(Event::Output(value));
+            },
+            // 5, 6
+            Jit | Jif => {
+                let val = self.get_value(start, instr, 0)?;
+                let cond = match opcode {
+                    Jit => !val.is_zero(),
+                    Jif => val.is_zero(),
+                    _ => unreachable!(),
+                };
+                let value = self.get_value(start, instr, 1)?;
+                if cond {
+                    let value = value
+                        .to_value()
+                        .ok_or(Error::Overflow { ip: start })?;
+                    self.ip = usize::try_from(value).map_err(|_| {
+                        Error::NegativeAddress { ip: start, address: value }
+                    })?;
+                }
+                effect.taken = Some(cond);
+            },
+            // 7, 8
+            Lt | Eq => {
+                let a = self.get_value(start, instr, 0)?;
+                let b = self.get_value(start, instr, 1)?;
+                let cond = match opcode {
+                    Lt => a < b,
+                    Eq => a == b,
+                    _ => unreachable!(),
+                };
+                store!(2, W::from_value(cond.into()));
+            },
+            // 9
+            Set => {
+                let offset = self
+                    .get_value(start, instr, 0)?
+                    .to_value()
+                    .ok_or(Error::Overflow { ip: start })?;
+                self.rp = self
+                    .rp
+                    .checked_add(offset)
+                    .ok_or(Error::Overflow { ip: start })?;
+            },
+            // 99
+            Halt => effect.event = Some(Event::Halted),
+            Custom { code, .. } => {
+                let handler = self.custom.handler(code);
+                effect.event = handler(&mut Operands::new(self, start, instr))?;
+                if let Some(Event::Output(x)) = &effect.event {
+                    self.record(Entry::Output, x);
+                }
+            },
         }
-
-        let _ = output.send(Signal::Halting);
-        Ok(())
+        Ok(effect)
     }
 
-    fn get_opcode(&mut self) -> aoc::Result<Opcode> {
-        let mut instruction = self.mem[self.ip];
-        let opcode = Opcode::try_from(instruction % 100)?;
-        let mut mode = [Mode::Positional; 3];
-        instruction /= 100;
-        for m in &mut mode {
-            *m = Mode::try_from(instruction % 10)?;
-            instruction /= 10;
+    #[inline]
+    fn record(&mut self, entry: fn(W) -> Entry<W>, value: &W) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript.entries.push(entry(value.clone()));
         }
+    }
 
-        self.instr = Instruction { mp: 0, modes: mode };
-        self.ip += 1;
-        Ok(opcode)
+    /// Looks up the instruction at `ip` in the decode cache, decoding it on
+    /// a miss and caching it if the machine has been running for a while.
+    #[inline(always)]
+    fn fetch(&mut self, ip: usize) -> Result<Decoded<W>, Error> {
+        if let Some(instr) = self.cache.get(ip) {
+            return Ok(instr.clone());
+        }
+        let instr = Decoded::new(&self.mem, ip, &self.custom)?;
+        if self.executed >= LAZY_CACHE_AFTER && self.cache.is_enabled() {
+            let limit = self.mem.dense().len();
+            self.cache.insert(ip, instr.clone(), limit);
+        }
+        Ok(instr)
     }
 
-    fn get_value(&mut self) -> aoc::Result<Value> {
+    #[inline(always)]
+    fn get_value(
+        &self,
+        ip: usize,
+        instr: &Decoded<W>,
+        i: usize,
+    ) -> Result<W, Error> {
         use Mode::*;
-        let address = match self.instr.modes[self.instr.mp] {
-            Positional | Relative => self.get_address()?,
-            Immediate => {
-                let ip = self.ip;
-                self.ip += 1;
-                self.instr.mp += 1;
-                ip
+        let value = match instr.modes[i] {
+            Positional | Relative => {
+                let address = self.get_address(ip, instr, i)?;
+                let value = self.mem.get(address);
+                log::debug!("[{}] = {}", address, value);
+                value
             },
+            Immediate => instr.params[i].clone(),
         };
-        let value = self.mem.get(address).copied().unwrap_or_default();
-        log::debug!("[{}] = {}", address, value);
         Ok(value)
     }
 
-    fn get_address(&mut self) -> aoc::Result<usize> {
+    #[inline(always)]
+    fn get_address(
+        &self,
+        ip: usize,
+        instr: &Decoded<W>,
+        i: usize,
+    ) -> Result<usize, Error> {
         use Mode::*;
-        let address = match self.instr.modes[self.instr.mp] {
-            Positional => usize::try_from(self.mem[self.ip])?,
-            Relative => usize::try_from(self.rp + self.mem[self.ip])?,
-            Immediate =>
-                return aoc::err!(
-                    "Writing results in immediate mode does not make sense."
-                ),
+        let param = instr.params[i].to_value().ok_or(Error::Overflow { ip })?;
+        let address = match instr.modes[i] {
+            Positional => param,
+            Relative =>
+                self.rp.checked_add(param).ok_or(Error::Overflow { ip })?,
+            Immediate => return Err(Error::ImmediateWrite { ip }),
         };
-        self.ip += 1;
-        self.instr.mp += 1;
-        Ok(address)
+        usize::try_from(address)
+            .map_err(|_| Error::NegativeAddress { ip, address })
     }
 
-    fn assign_expand(&mut self, address: usize, value: Value) {
-        if address >= self.mem.len() {
-            self.mem.resize_with(address + 1, Default::default);
+    #[inline(always)]
+    fn assign_expand(
+        &mut self,
+        ip: usize,
+        address: usize,
+        value: W,
+    ) -> Result<(), Error> {
+        if let Some(undo) = &mut self.undo {
+            undo.write(address, self.mem.get(address));
         }
-        self.mem[address] = value;
+        self.assign(ip, address, value)
+    }
+
+    /// Like `assign_expand`, but leaves nothing to undo.
+    #[inline(always)]
+    fn assign(
+        &mut self,
+        ip: usize,
+        address: usize,
+        value: W,
+    ) -> Result<(), Error> {
         log::debug!("[{}] := {}", address, value);
+        self.mem
+            .set(address, value)
+            .map_err(|_| Error::MemoryLimit { ip, address })?;
+        self.cache.invalidate(address);
+        Ok(())
     }
+}
 
-    pub fn spawn(mut self) -> (mpsc::Sender<Signal>, mpsc::Receiver<Signal>) {
-        let ends = self.setup_io();
-        rayon::spawn(move || self.run().unwrap());
-        ends
+/// Splits an instruction word into its opcode and parameter modes.
+pub fn decode(word: Value) -> Option<(Opcode, [Mode; 3])> {
+    decode_word(0, word).ok()
+}
+
+#[inline]
+fn decode_word(ip: usize, word: Value) -> Result<(Opcode, [Mode; 3]), Error> {
+    use Opcode::*;
+    // Matched directly rather than through `TryFrom`, whose boxed errors are
+    // too slow for the interpreter loop.
+    let opcode = match word % 100 {
+        1 => Add,
+        2 => Mul,
+        3 => Read,
+        4 => Write,
+        5 => Jit,
+        6 => Jif,
+        7 => Lt,
+        8 => Eq,
+        9 => Set,
+        99 => Halt,
+        _ => return Err(Error::UnknownOpcode { ip, value: word }),
+    };
+    Ok((opcode, decode_modes(ip, word)?))
+}
+
+#[inline]
+fn decode_modes(ip: usize, word: Value) -> Result<[Mode; 3], Error> {
+    let mut modes = [Mode::Positional; 3];
+    let mut digits = word / 100;
+    for m in &mut modes {
+        *m = match digits % 10 {
+            0 => Mode::Positional,
+            1 => Mode::Immediate,
+            2 => Mode::Relative,
+            _ => return Err(Error::InvalidMode { ip, value: word }),
+        };
+        digits /= 10;
+    }
+    Ok(modes)
+}
+
+/// Inverse of `decode`: packs an opcode and its parameter modes into a word.
+pub fn encode(opcode: Opcode, modes: &[Mode]) -> Value {
+    let modes = modes.iter().rev().fold(0, |acc, &m| acc * 10 + Value::from(m));
+    modes * 100 + Value::from(opcode)
+}
+
+impl From<Error> for ExitReason {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::BudgetExhausted { .. } => ExitReason::BudgetExhausted,
+            Error::DeadlineExceeded { .. } => ExitReason::DeadlineExceeded,
+            e => ExitReason::Faulted(e),
+        }
+    }
+}
+
+impl Exit {
+    /// Turns a fault into an error, so that `?` can be used on a run.
+    pub fn into_result(self) -> Result<Self, Error> {
+        match self.reason {
+            ExitReason::Faulted(e) => Err(e),
+            _ => Ok(self),
+        }
     }
 }
 
@@ -321,6 +924,7 @@ impl Signal {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::iter;
 
     #[test_log::new]
     fn add_mul() -> aoc::Result<()> {
@@ -330,9 +934,8 @@ mod test {
             expected: &[Value],
         ) -> aoc::Result<()> {
             vm.read_mem(input);
-            let _ = vm.setup_io();
-            vm.run()?;
-            assert_eq!(vm.mem.as_slice(), expected);
+            vm.run_with(io::Iter(iter::empty()), Vec::new()).into_result()?;
+            assert_eq!(vm.mem(), expected);
             Ok(())
         }
 
@@ -362,13 +965,10 @@ mod test {
             expected: Value,
         ) -> aoc::Result<()> {
             vm.read_program(program)?;
-            let (input, output) = vm.setup_io();
-            input.send(Signal::Value(input_value))?;
-            vm.run()?;
-            let results =
-                output.iter().filter(Signal::is_value).collect::<Vec<Signal>>();
-            assert_eq!(results.len(), 1);
-            assert_eq!(results[0], Signal::Value(expected));
+            let mut output = Vec::new();
+            vm.run_with(io::Iter(iter::once(input_value)), &mut output)
+                .into_result()?;
+            assert_eq!(output, [expected]);
             Ok(())
         }
 
@@ -381,26 +981,311 @@ mod test {
         Ok(())
     }
 
+    #[test_log::new]
+    fn resume() -> aoc::Result<()> {
+        let mut vm = VM::with_program("3,9,8,9,10,9,4,9,99,-1,8")?;
+        assert_eq!(vm.resume()?, Event::NeedsInput);
+        assert_eq!(vm.resume()?, Event::NeedsInput);
+        vm.provide_input(8);
+        assert_eq!(vm.resume()?, Event::Output(1));
+        assert_eq!(vm.resume()?, Event::Halted);
+        assert_eq!(vm.resume()?, Event::Halted);
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn clone_and_restore() -> aoc::Result<()> {
+        let mut vm = VM::with_program("3,9,8,9,10,9,4,9,99,-1,8")?;
+        assert_eq!(vm.resume()?, Event::NeedsInput);
+        let snapshot = vm.snapshot();
+
+        let mut fork = vm.clone();
+        fork.provide_input(7);
+        assert_eq!(fork.resume()?, Event::Output(0));
+
+        vm.provide_input(8);
+        assert_eq!(vm.resume()?, Event::Output(1));
+        assert_eq!(vm.resume()?, Event::Halted);
+
+        let mut restored = VM::new();
+        restored.restore(&snapshot.to_string().parse()?)?;
+        assert_eq!(restored.snapshot(), snapshot);
+        restored.provide_input(8);
+        assert_eq!(restored.resume()?, Event::Output(1));
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn faults() {
+        fn fault(mem: &[Value]) -> Error {
+            VM::with_mem(mem).resume().unwrap_err()
+        }
+
+        assert_eq!(fault(&[98]), Error::UnknownOpcode { ip: 0, value: 98 });
+        assert_eq!(
+            fault(&[301, 0, 0, 0]),
+            Error::InvalidMode { ip: 0, value: 301 }
+        );
+        assert_eq!(fault(&[11101, 1, 1, 0]), Error::ImmediateWrite { ip: 0 });
+        assert_eq!(
+            fault(&[1101, 1, 1, -3]),
+            Error::NegativeAddress { ip: 0, address: -3 }
+        );
+        assert_eq!(fault(&[1102, Value::MAX, 2, 0]), Error::Overflow { ip: 0 });
+        assert_eq!(fault(&[1105, 1, 7]), Error::IpOutOfBounds { ip: 7 });
+        assert_eq!(fault(&[1, 0, 0]), Error::IpOutOfBounds { ip: 0 });
+
+        // A fault leaves the machine on the instruction, with its input.
+        let mut vm = VM::with_mem(&[1102, Value::MAX, 2, 0, 99]);
+        assert_eq!(vm.resume(), Err(Error::Overflow { ip: 0 }));
+        assert_eq!((vm.ip(), vm.instructions_executed()), (0, 0));
+        let mut vm = VM::with_mem(&[1101, 2, 3, 8, 1102, Value::MAX, 2, 0]);
+        assert_eq!(
+            vm.run_with(io::Iter(iter::empty()), Vec::new()),
+            Exit {
+                reason: ExitReason::Faulted(Error::Overflow { ip: 4 }),
+                ip: 4,
+                executed: 1
+            }
+        );
+        let mut vm = VM::with_mem(&[103, 5, 99]);
+        vm.provide_input(42);
+        assert_eq!(vm.resume(), Err(Error::ImmediateWrite { ip: 0 }));
+        assert_eq!(vm.ip(), 0);
+        assert_eq!(vm.pending_input(), &[42]);
+        vm.poke(0, 3).unwrap();
+        assert_eq!(vm.resume(), Ok(Event::Halted));
+        assert_eq!((vm.peek(5), vm.pending_input().len()), (42, 0));
+
+        let mut vm = VM::with_mem(&[3, 0, 99]);
+        let exit = vm.run();
+        assert_eq!(
+            exit.reason,
+            ExitReason::Faulted(Error::NotConnected { ip: 0 })
+        );
+    }
+
+    #[test_log::new]
+    fn budget_and_deadline() {
+        // Counts in [9] forever.
+        let program = [1001, 9, 1, 9, 1105, 1, 0, 99, 0, 0];
+
+        let mut vm = VM::with_mem(&program);
+        vm.set_instruction_budget(Some(101));
+        assert_eq!(
+            vm.resume(),
+            Err(Error::BudgetExhausted { ip: 4, executed: 101 })
+        );
+        assert_eq!(vm.peek(9), 51);
+        vm.set_instruction_budget(Some(102));
+        assert_eq!(
+            vm.resume(),
+            Err(Error::BudgetExhausted { ip: 0, executed: 102 })
+        );
+        assert_eq!(vm.instructions_executed(), 102);
+
+        let mut vm = VM::with_mem(&program);
+        vm.set_instruction_budget(Some(10));
+        let exit = vm.run_with(io::Iter(iter::empty()), Vec::new());
+        assert_eq!(exit.reason, ExitReason::BudgetExhausted);
+        assert_eq!((exit.ip, exit.executed), (0, 10));
+
+        let mut vm = VM::with_mem(&program);
+        vm.set_deadline(Some(Instant::now()));
+        assert_eq!(
+            vm.resume(),
+            Err(Error::DeadlineExceeded { ip: 0, executed: 0 })
+        );
+        let timeout = std::time::Duration::from_millis(10);
+        vm.set_deadline(Some(Instant::now() + timeout));
+        match vm.resume() {
+            Err(Error::DeadlineExceeded { executed, .. }) =>
+                assert!(executed.is_multiple_of(DEADLINE_INTERVAL)),
+            result => panic!("Expected a timeout, got {:?}", result),
+        }
+    }
+
+    #[test_log::new]
+    fn self_modifying() -> aoc::Result<()> {
+        // Outputs 1, patches the parameter of its first instruction and
+        // loops back to output 2.
+        let program = assemble(
+            r#"
+            start:  out  #1
+                    jt   [flag], #end
+                    add  #2, #0, [1]
+                    add  #1, #0, [flag]
+                    jt   #1, #start
+            end:    hlt
+            flag:   db   0
+            "#,
+        )?;
+        fn outputs(vm: &mut VM) -> aoc::Result<Vec<Value>> {
+            let mut output = Vec::new();
+            vm.run_with(io::Iter(iter::empty()), &mut output).into_result()?;
+            Ok(output)
+        }
+
+        let mut vm = VM::with_mem(&program);
+        assert_eq!(outputs(&mut vm)?, [1, 2]);
+        vm.poke(1, 5)?;
+        vm.set_ip(0);
+        assert_eq!(outputs(&mut vm)?, [5]);
+
+        let mut template = VM::with_mem(&program);
+        template.precompile();
+        for _ in 0..2 {
+            assert_eq!(outputs(&mut template.clone())?, [1, 2]);
+        }
+
+        let mut vm = VM::with_mem(&program);
+        vm.set_decode_cache(false);
+        assert_eq!(outputs(&mut vm)?, [1, 2]);
+
+        // Long enough for the machine to fill the cache by itself.
+        let mut vm = VM::with_mem(&program);
+        while vm.instructions_executed() < 2 * LAZY_CACHE_AFTER {
+            vm.read_mem(&program);
+            assert_eq!(outputs(&mut vm)?, [1, 2]);
+        }
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn wide_words() -> aoc::Result<()> {
+        // Squares 3 `n` times, i.e. computes 3^(2^n).
+        fn power_tower<W: Word>(n: Value) -> Result<Event<W>, Error> {
+            let program = assemble(&format!(
+                r#"
+                loop:   mul  [x], [x], [x]
+                        add  [n], #-1, [n]
+                        jt   [n], #loop
+                        out  [x]
+                        hlt
+                x:      db   3
+                n:      db   {}
+                "#,
+                n
+            ))
+            .unwrap();
+            let mut vm = VM::<W>::default();
+            let mem = program.into_iter().map(W::from_value);
+            vm.read_mem(&mem.collect::<Vec<_>>());
+            vm.resume()
+        }
+
+        assert_eq!(power_tower::<Value>(5)?, Event::Output(1853020188851841));
+        assert_eq!(power_tower::<Value>(6), Err(Error::Overflow { ip: 0 }));
+        assert_eq!(
+            power_tower::<i128>(6)?,
+            Event::Output(3433683820292512484657849089281)
+        );
+
+        #[cfg(feature = "bigint")]
+        {
+            let x = match power_tower::<num_bigint::BigInt>(7)? {
+                Event::Output(x) => x,
+                e => panic!("Unexpected event: {:?}", e),
+            };
+            assert_eq!(x, num_bigint::BigInt::from(3).pow(128));
+        }
+
+        let mut vm = VM::<i128>::default();
+        vm.read_program("109,1,204,-1,99")?;
+        assert_eq!(vm.resume()?, Event::Output(109));
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn sparse_memory() -> aoc::Result<()> {
+        // Stores to and loads from an address far beyond the program.
+        let program = [1101, 6, 7, 1 << 40, 4, 1 << 40, 99];
+        let mut vm = VM::with_mem(&program);
+        assert_eq!(vm.resume()?, Event::Output(13));
+        assert_eq!(vm.mem().len(), program.len());
+        assert_eq!(vm.memory_stats().pages, 1);
+
+        let snapshot: Snapshot = vm.snapshot().to_string().parse()?;
+        assert_eq!(snapshot.sparse, [(1 << 40, 13)]);
+        let mut restored = VM::new();
+        restored.restore(&snapshot)?;
+        assert_eq!(restored.peek(1 << 40), 13);
+        let mut limited = VM::new();
+        limited.set_memory_limit(Some(PAGE_SIZE));
+        assert_eq!(
+            limited.restore(&snapshot),
+            Err(Error::MemoryLimit { ip: snapshot.ip, address: 1 << 40 })
+        );
+
+        let mut vm = VM::with_mem(&program);
+        vm.set_memory_limit(Some(PAGE_SIZE));
+        assert_eq!(
+            vm.resume(),
+            Err(Error::MemoryLimit { ip: 0, address: 1 << 40 })
+        );
+        assert_eq!(vm.poke(10, 1), Ok(()));
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn io_devices() -> aoc::Result<()> {
+        const DOUBLER: &str = "3,11,1002,11,2,11,4,11,1105,1,0,0";
+
+        let mut vm = VM::with_program(DOUBLER)?;
+        let (input, output) = vm.setup_io();
+        input.send(Signal::Value(4))?;
+        drop(input);
+        assert_eq!(
+            vm.run(),
+            Exit { reason: ExitReason::InputClosed, ip: 0, executed: 4 }
+        );
+        assert_eq!(
+            output.iter().collect::<Vec<_>>(),
+            [Signal::Value(8), Signal::Halting]
+        );
+
+        let mut vm = VM::with_program(DOUBLER)?;
+        let mut input = io::Tape::new(VecDeque::from(vec![1, 2, 3]));
+        let mut output = io::Tape::new(VecDeque::new());
+        vm.run_with(&mut input, &mut output).into_result()?;
+        assert_eq!(input.values, [1, 2, 3]);
+        assert_eq!(output.values, [2, 4, 6]);
+
+        let mut vm = VM::with_program(DOUBLER)?;
+        let mut n = 0;
+        let mut sum = 0;
+        let input = io::Func(|| {
+            n += 1;
+            if n <= 10 {
+                Some(n)
+            } else {
+                None
+            }
+        });
+        vm.run_with(input, io::Func(|x| sum += x)).into_result()?;
+        assert_eq!(sum, 110);
+        Ok(())
+    }
+
     #[test_log::new]
     fn diagnostic_program() -> aoc::Result<()> {
         const DIAGNOSTIC: &str = include_str!("../../day05/input.txt");
 
         let mut vm = VM::with_program(DIAGNOSTIC)?;
-        let (input, output) = vm.setup_io();
-        input.send(Signal::Value(1))?;
-        vm.run()?;
-        let results =
-            output.iter().filter(Signal::is_value).collect::<Vec<Signal>>();
+        let mut results = Vec::new();
+        vm.run_with(io::Iter(iter::once(1)), &mut results).into_result()?;
         let n = results.len() - 1;
         for &test_result in &results[..n] {
-            assert_eq!(test_result, Signal::Value(0));
+            assert_eq!(test_result, 0);
         }
-        assert_eq!(results[n], Signal::Value(4887191));
+        assert_eq!(results[n], 4887191);
 
         vm.read_program(DIAGNOSTIC)?;
         let (input, output) = vm.setup_io();
         input.send(Signal::Value(5))?;
-        vm.run()?;
+        let exit = vm.run();
+        assert_eq!(exit.reason, ExitReason::Halted);
+        assert_eq!(vm.peek(exit.ip), 99);
         let results = output.iter().collect::<Vec<Signal>>();
         assert_eq!(results[0], Signal::Value(3419022));
         Ok(())
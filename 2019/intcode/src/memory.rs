@@ -0,0 +1,325 @@
+use crate::{Value, Word};
+use std::{collections::HashMap, fmt};
+
+pub const PAGE_SIZE: usize = 1024;
+
+/// Writes this far past the dense region grow it; anything further away
+/// lands in a sparse page instead.
+const DENSE_SLACK: usize = 4 * PAGE_SIZE;
+
+/// Intcode memory: a dense region holding the program and whatever is
+/// written close to it, plus sparse pages for far away addresses. Cells
+/// that were never written read as zero.
+#[derive(Clone, Debug, Default)]
+pub struct Memory<W = Value> {
+    dense: Vec<W>,
+    pages: HashMap<usize, Box<[W]>>,
+    limit: Option<usize>,
+    stats: MemoryStats,
+    /// Bitset of dense pages written since the last `load` or `reset`.
+    dirty: Vec<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryStats {
+    /// Words currently allocated, dense and sparse.
+    pub allocated: usize,
+    /// Largest number of words allocated at any point.
+    pub peak_allocated: usize,
+    /// Highest address written to, if any.
+    pub highest_address: Option<usize>,
+    pub pages: usize,
+}
+
+/// Returned when a write would allocate more words than the configured
+/// limit allows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LimitExceeded;
+
+impl<W: Word> Memory<W> {
+    pub fn dense(&self) -> &[W] {
+        &self.dense
+    }
+
+    pub fn dense_mut(&mut self) -> &mut [W] {
+        // Any page may be written through the slice.
+        self.mark_all_dirty();
+        &mut self.dense
+    }
+
+    /// Replaces the contents with `mem`, dropping all sparse pages. Every
+    /// page counts as written, since `mem` need not be what the next `reset`
+    /// brings back.
+    pub fn load(&mut self, mem: &[W]) {
+        self.dense.clear();
+        self.dense.extend_from_slice(mem);
+        self.clear();
+        self.mark_all_dirty();
+    }
+
+    /// Replaces the contents with `image`, dropping all sparse pages. If the
+    /// last `reset` was given `image` too, only the pages that were written
+    /// since are copied.
+    pub fn reset(&mut self, image: &[W]) {
+        if self.dense.len() < image.len() {
+            self.dense.clear();
+            self.dense.extend_from_slice(image);
+            return self.clear();
+        }
+        self.dense.truncate(image.len());
+        for (i, &bits) in self.dirty.iter().enumerate() {
+            for bit in (0..64).filter(|bit| (bits >> bit) & 1 != 0) {
+                let start = (i * 64 + bit) * PAGE_SIZE;
+                if start >= image.len() {
+                    break;
+                }
+                let end = image.len().min(start + PAGE_SIZE);
+                self.dense[start..end].clone_from_slice(&image[start..end]);
+            }
+        }
+        self.clear();
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Caps the number of allocated words. Memory that is already allocated
+    /// is kept even if it exceeds the new limit.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        self.stats
+    }
+
+    /// Reads a cell, or `None` if it was never allocated.
+    #[inline]
+    pub fn fetch(&self, address: usize) -> Option<W> {
+        if let Some(x) = self.dense.get(address) {
+            return Some(x.clone());
+        }
+        let page = self.pages.get(&(address / PAGE_SIZE))?;
+        Some(page[address % PAGE_SIZE].clone())
+    }
+
+    #[inline]
+    pub fn get(&self, address: usize) -> W {
+        self.fetch(address).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn set(
+        &mut self,
+        address: usize,
+        value: W,
+    ) -> Result<(), LimitExceeded> {
+        if address < self.dense.len() {
+            // Allocation is unchanged, so only the highest address can move.
+            self.dense[address] = value;
+            self.touch(address);
+            self.mark_dirty(address);
+            return Ok(());
+        }
+        if address < self.dense.len() + DENSE_SLACK {
+            self.grow_dense(address)?;
+            self.dense[address] = value;
+            self.mark_dirty(address);
+        } else {
+            let index = address / PAGE_SIZE;
+            if !self.pages.contains_key(&index) {
+                self.reserve(PAGE_SIZE)?;
+                let page = vec![W::default(); PAGE_SIZE];
+                self.pages.insert(index, page.into());
+            }
+            self.pages.get_mut(&index).unwrap()[address % PAGE_SIZE] = value;
+        }
+
+        self.touch(address);
+        self.update_stats();
+        Ok(())
+    }
+
+    /// Sparse cells as `(address, value)` pairs, skipping zeroes.
+    pub fn sparse(&self) -> Vec<(usize, W)> {
+        let mut cells = self
+            .pages
+            .iter()
+            .flat_map(|(&index, page)| {
+                page.iter()
+                    .enumerate()
+                    .filter(|(_, x)| !x.is_zero())
+                    .map(move |(i, x)| (index * PAGE_SIZE + i, x.clone()))
+            })
+            .collect::<Vec<_>>();
+        cells.sort_unstable_by_key(|&(address, _)| address);
+        cells
+    }
+
+    /// Grows the dense region to the page boundary after `address`, pulling
+    /// in any sparse pages it now covers.
+    fn grow_dense(&mut self, address: usize) -> Result<(), LimitExceeded> {
+        let old_len = self.dense.len();
+        let new_len = (address / PAGE_SIZE + 1) * PAGE_SIZE;
+        let covered = self
+            .pages
+            .keys()
+            .filter(|&&index| index * PAGE_SIZE < new_len)
+            .count();
+        self.reserve((new_len - old_len).saturating_sub(covered * PAGE_SIZE))?;
+
+        self.dense.resize(new_len, W::default());
+        let dense = &mut self.dense;
+        self.pages.retain(|&index, page| {
+            let start = index * PAGE_SIZE;
+            if start >= new_len {
+                return true;
+            }
+            dense[start..start + PAGE_SIZE].clone_from_slice(page);
+            false
+        });
+        Ok(())
+    }
+
+    fn reserve(&self, words: usize) -> Result<(), LimitExceeded> {
+        match self.limit {
+            Some(limit) if self.allocated() + words > limit =>
+                Err(LimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    fn allocated(&self) -> usize {
+        self.dense.len() + self.pages.len() * PAGE_SIZE
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self, address: usize) {
+        let page = address / PAGE_SIZE;
+        if self.dirty.len() <= page / 64 {
+            self.dirty.resize(page / 64 + 1, 0);
+        }
+        self.dirty[page / 64] |= 1 << (page % 64);
+    }
+
+    fn mark_all_dirty(&mut self) {
+        let pages = self.dense.len().div_ceil(PAGE_SIZE);
+        self.dirty = vec![!0; pages / 64 + 1];
+    }
+
+    /// Drops sparse pages and dirty marks after the dense region was
+    /// replaced.
+    fn clear(&mut self) {
+        self.pages.clear();
+        self.dirty.clear();
+        self.stats.highest_address = None;
+        self.update_stats();
+    }
+
+    #[inline]
+    fn touch(&mut self, address: usize) {
+        let highest = &mut self.stats.highest_address;
+        if highest.is_none_or(|h| h < address) {
+            *highest = Some(address);
+        }
+    }
+
+    fn update_stats(&mut self) {
+        let allocated = self.allocated();
+        self.stats.allocated = allocated;
+        self.stats.peak_allocated = self.stats.peak_allocated.max(allocated);
+        self.stats.pages = self.pages.len();
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Memory limit exceeded")
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test_log::new]
+    fn sparse_and_dense() {
+        let mut mem: Memory = Memory::default();
+        mem.load(&[1, 2, 3]);
+        mem.set(10, 4).unwrap();
+        assert_eq!(mem.dense().len(), PAGE_SIZE);
+        assert_eq!(mem.get(10), 4);
+
+        let far = 1 << 40;
+        mem.set(far, 5).unwrap();
+        assert_eq!(mem.get(far), 5);
+        assert_eq!(mem.get(far + 1), 0);
+        assert_eq!(mem.fetch(far + PAGE_SIZE), None);
+        assert_eq!(mem.sparse(), [(far, 5)]);
+        assert_eq!(
+            mem.stats(),
+            MemoryStats {
+                allocated: 2 * PAGE_SIZE,
+                peak_allocated: 2 * PAGE_SIZE,
+                highest_address: Some(far),
+                pages: 1,
+            }
+        );
+
+        // A page that the dense region grows into is merged into it.
+        mem.set(6 * PAGE_SIZE, 6).unwrap();
+        mem.set(3 * PAGE_SIZE, 7).unwrap();
+        assert_eq!(mem.stats().pages, 2);
+        mem.set(5 * PAGE_SIZE, 8).unwrap();
+        assert_eq!(mem.stats().pages, 2);
+        assert_eq!(mem.dense().len(), 6 * PAGE_SIZE);
+        assert_eq!(mem.get(6 * PAGE_SIZE), 6);
+        mem.set(6 * PAGE_SIZE + 1, 9).unwrap();
+        assert_eq!(mem.dense().len(), 7 * PAGE_SIZE);
+        assert_eq!(mem.get(6 * PAGE_SIZE), 6);
+        assert_eq!(mem.stats().pages, 1);
+    }
+
+    #[test_log::new]
+    fn limit() {
+        let mut mem: Memory = Memory::default();
+        mem.load(&[0; 10]);
+        mem.set_limit(Some(PAGE_SIZE + 10));
+        assert_eq!(mem.set(1 << 40, 1), Ok(()));
+        assert_eq!(mem.set(1 << 41, 1), Err(LimitExceeded));
+        assert_eq!(mem.set(20, 1), Err(LimitExceeded));
+        assert_eq!(mem.set(9, 1), Ok(()));
+    }
+
+    #[test_log::new]
+    fn reset() {
+        let image = (0..3 * PAGE_SIZE as i64).collect::<Vec<_>>();
+        let mut mem: Memory = Memory::default();
+        mem.reset(&image);
+        mem.set(5, -1).unwrap();
+        mem.set(3 * PAGE_SIZE + 2, -1).unwrap();
+        mem.set(1 << 40, -1).unwrap();
+        mem.dense[PAGE_SIZE] = -1;
+        mem.reset(&image);
+        assert_eq!(mem.dense().len(), image.len());
+        assert_eq!(mem.dense()[..PAGE_SIZE], image[..PAGE_SIZE]);
+        // Pages that were not written through `set` are left alone.
+        assert_eq!(mem.get(PAGE_SIZE), -1);
+        assert_eq!(mem.stats().pages, 0);
+        assert_eq!(mem.stats().highest_address, None);
+
+        mem.dense_mut()[PAGE_SIZE] = -1;
+        mem.reset(&image);
+        assert_eq!(mem.dense(), &image[..]);
+
+        // Anything loaded may differ from the image on every page.
+        let mut other = image.clone();
+        other[2 * PAGE_SIZE] = -1;
+        mem.load(&other);
+        mem.reset(&image);
+        assert_eq!(mem.dense(), &image[..]);
+    }
+}
@@ -0,0 +1,322 @@
+//! Deterministic, single threaded networks of intcode machines that talk in
+//! `(destination, x, y)` packets.
+
+use crate::{Error, Event, Value, VM};
+use std::{collections::VecDeque, convert::TryFrom};
+
+pub const NAT_ADDRESS: Value = 255;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub src: Value,
+    pub dest: Value,
+    pub x: Value,
+    pub y: Value,
+}
+
+/// What the network should do after consulting its monitor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action<T> {
+    Continue,
+    Send(Packet),
+    Stop(T),
+}
+
+/// Why `Network::run` returned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome<T> {
+    Stopped(T),
+    Idle,
+    Halted,
+}
+
+/// Observes the network from outside. Packets addressed to anything other
+/// than a node are handed to the monitor, and it gets a chance to wake the
+/// network up whenever it goes idle.
+pub trait Monitor {
+    type Output;
+
+    fn receive(&mut self, packet: Packet) -> Action<Self::Output>;
+
+    fn idle(&mut self) -> Action<Self::Output>;
+}
+
+/// The NAT from 2019 day 23: it remembers the last packet sent to it and
+/// forwards that packet to node 0 whenever the network is idle. It stops
+/// the network with `y` once it delivers the same `y` twice in a row.
+#[derive(Clone, Debug, Default)]
+pub struct Nat {
+    last: Option<Packet>,
+    delivered: Option<Value>,
+}
+
+struct Node {
+    vm: VM,
+    queue: VecDeque<(Value, Value)>,
+    outbox: Vec<Value>,
+    halted: bool,
+}
+
+pub struct Network<M> {
+    nodes: Vec<Node>,
+    monitor: M,
+    idle_rounds: u32,
+}
+
+impl Monitor for Nat {
+    type Output = Value;
+
+    fn receive(&mut self, packet: Packet) -> Action<Value> {
+        if packet.dest == NAT_ADDRESS {
+            self.last = Some(packet);
+        } else {
+            log::warn!("Dropping packet to unknown address: {:?}", packet);
+        }
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action<Value> {
+        let packet = match self.last {
+            Some(p) => Packet { src: NAT_ADDRESS, dest: 0, ..p },
+            None => return Action::Continue,
+        };
+        if self.delivered == Some(packet.y) {
+            return Action::Stop(packet.y);
+        }
+        self.delivered = Some(packet.y);
+        Action::Send(packet)
+    }
+}
+
+impl<M: Monitor> Network<M> {
+    /// Boots `size` copies of `program`, giving each its address as the
+    /// first input.
+    pub fn new(program: &[Value], size: usize, monitor: M) -> Self {
+        let nodes = (0..size)
+            .map(|address| {
+                let mut vm = VM::with_mem(program);
+                vm.provide_input(address as Value);
+                Node {
+                    vm,
+                    queue: VecDeque::new(),
+                    outbox: Vec::with_capacity(3),
+                    halted: false,
+                }
+            })
+            .collect();
+        Network { nodes, monitor, idle_rounds: 2 }
+    }
+
+    /// Number of consecutive quiet rounds before the network counts as idle.
+    pub fn with_idle_rounds(mut self, rounds: u32) -> Self {
+        self.idle_rounds = rounds.max(1);
+        self
+    }
+
+    pub fn monitor(&self) -> &M {
+        &self.monitor
+    }
+
+    pub fn node(&self, address: usize) -> &VM {
+        &self.nodes[address].vm
+    }
+
+    /// Runs the nodes round robin until the monitor stops the network, the
+    /// network stays idle without the monitor intervening, or every node
+    /// has halted.
+    pub fn run(&mut self) -> Result<Outcome<M::Output>, Error> {
+        let mut quiet = 0;
+        loop {
+            if self.nodes.iter().all(|n| n.halted) {
+                return Ok(Outcome::Halted);
+            }
+
+            let mut busy = false;
+            for address in 0..self.nodes.len() {
+                let (sent, packets) = self.tick(address)?;
+                busy |= sent;
+                for packet in packets {
+                    if let Action::Stop(x) = self.route(packet) {
+                        return Ok(Outcome::Stopped(x));
+                    }
+                }
+            }
+
+            quiet = if busy { 0 } else { quiet + 1 };
+            if quiet < self.idle_rounds {
+                continue;
+            }
+            quiet = 0;
+            match self.monitor.idle() {
+                Action::Continue => return Ok(Outcome::Idle),
+                Action::Send(packet) => self.deliver(packet),
+                Action::Stop(x) => return Ok(Outcome::Stopped(x)),
+            }
+        }
+    }
+
+    /// Feeds a node its next packet, or -1 if it has none, and runs it until
+    /// it waits for input again. Returns whether the node had or produced
+    /// any traffic, along with the packets it sent.
+    fn tick(&mut self, address: usize) -> Result<(bool, Vec<Packet>), Error> {
+        let node = &mut self.nodes[address];
+        if node.halted {
+            return Ok((false, Vec::new()));
+        }
+
+        let mut busy = match node.queue.pop_front() {
+            Some((x, y)) => {
+                node.vm.provide_input(x);
+                node.vm.provide_input(y);
+                true
+            },
+            None => {
+                node.vm.provide_input(-1);
+                false
+            },
+        };
+
+        let mut packets = Vec::new();
+        loop {
+            match node.vm.resume()? {
+                Event::Output(v) => {
+                    busy = true;
+                    node.outbox.push(v);
+                    if let [dest, x, y] = node.outbox[..] {
+                        let src = address as Value;
+                        packets.push(Packet { src, dest, x, y });
+                        node.outbox.clear();
+                    }
+                },
+                Event::NeedsInput => break,
+                Event::Halted => {
+                    node.halted = true;
+                    break;
+                },
+            }
+        }
+        Ok((busy || !node.queue.is_empty(), packets))
+    }
+
+    fn route(&mut self, packet: Packet) -> Action<M::Output> {
+        let nodes = self.nodes.len();
+        if usize::try_from(packet.dest).is_ok_and(|d| d < nodes) {
+            self.deliver(packet);
+            return Action::Continue;
+        }
+        match self.monitor.receive(packet) {
+            Action::Send(p) => {
+                self.deliver(p);
+                Action::Continue
+            },
+            action => action,
+        }
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        let node = usize::try_from(packet.dest)
+            .ok()
+            .and_then(|dest| self.nodes.get_mut(dest));
+        match node {
+            Some(node) => node.queue.push_back((packet.x, packet.y)),
+            None => log::warn!("Dropping undeliverable packet: {:?}", packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble;
+
+    struct FirstPacket;
+
+    impl Monitor for FirstPacket {
+        type Output = Packet;
+
+        fn receive(&mut self, packet: Packet) -> Action<Packet> {
+            Action::Stop(packet)
+        }
+
+        fn idle(&mut self) -> Action<Packet> {
+            Action::Continue
+        }
+    }
+
+    #[test_log::new]
+    fn relay() -> aoc::Result<()> {
+        // Node 0 starts a packet that every node forwards to the next
+        // address, incrementing y on the way.
+        let program = assemble(
+            r#"
+                    in   [addr]
+                    add  [addr], #1, [dest]
+                    jt   [addr], #loop
+                    out  [dest]
+                    out  #0
+                    out  #0
+            loop:   in   [x]
+                    eq   [x], #-1, [t]
+                    jt   [t], #loop
+                    in   [y]
+                    add  [y], #1, [y]
+                    out  [dest]
+                    out  [x]
+                    out  [y]
+                    jt   #1, #loop
+            addr:   db   0
+            dest:   db   0
+            x:      db   0
+            y:      db   0
+            t:      db   0
+            "#,
+        )?;
+
+        let mut network = Network::new(&program, 3, FirstPacket);
+        let outcome = network.run()?;
+        assert_eq!(
+            outcome,
+            Outcome::Stopped(Packet { src: 2, dest: 3, x: 0, y: 2 })
+        );
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn nat() -> aoc::Result<()> {
+        // Node 0 reports (0, 2) to the NAT and answers every non-zero y it
+        // receives with y - 1. Node 1 never talks.
+        let program = assemble(
+            r#"
+                    in   [addr]
+                    jt   [addr], #loop
+                    out  #255
+                    out  #0
+                    out  #2
+            loop:   in   [x]
+                    eq   [x], #-1, [t]
+                    jt   [t], #loop
+                    in   [y]
+                    jf   [y], #loop
+                    add  [y], #-1, [y]
+                    out  #255
+                    out  [x]
+                    out  [y]
+                    jt   #1, #loop
+            addr:   db   0
+            x:      db   0
+            y:      db   0
+            t:      db   0
+            "#,
+        )?;
+
+        let mut network = Network::new(&program, 2, Nat::default());
+        assert_eq!(network.run()?, Outcome::Stopped(0));
+
+        let mut network = Network::new(&program, 2, FirstPacket);
+        assert_eq!(
+            network.run()?,
+            Outcome::Stopped(Packet { src: 0, dest: 255, x: 0, y: 2 })
+        );
+        Ok(())
+    }
+}
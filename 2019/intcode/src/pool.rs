@@ -0,0 +1,213 @@
+use crate::{io, Cache, Error, Extensions, Memory, Value, Word, VM};
+use rayon::prelude::*;
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+
+/// A program that is parsed and decoded once, so that any number of
+/// machines can start from it cheaply.
+#[derive(Clone, Debug)]
+pub struct ProgramImage<W = Value> {
+    mem: Arc<[W]>,
+    cache: Cache<W>,
+}
+
+/// Machines running one program that are handed out again after use. A
+/// machine that comes back only has the pages it wrote to restored, so
+/// running many short computations does not copy the whole program each
+/// time.
+pub struct Pool<W = Value> {
+    image: ProgramImage<W>,
+    idle: Mutex<Vec<VM<W>>>,
+}
+
+/// A machine borrowed from a `Pool`, which it returns to when dropped.
+pub struct Pooled<'a, W: Word = Value> {
+    pool: &'a Pool<W>,
+    vm: Option<VM<W>>,
+}
+
+impl<W: Word> ProgramImage<W> {
+    pub fn new(mem: &[W]) -> Self {
+        let mut memory = Memory::default();
+        memory.load(mem);
+        let mut cache = Cache::default();
+        cache.compile(&memory, &Extensions::default());
+        ProgramImage { mem: mem.into(), cache }
+    }
+
+    pub fn parse(program: &str) -> aoc::Result<Self>
+    where
+        W::Err: std::error::Error + 'static,
+    {
+        let mem = program
+            .trim()
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<W>, _>>()?;
+        Ok(ProgramImage::new(&mem))
+    }
+
+    pub fn mem(&self) -> &[W] {
+        &self.mem
+    }
+
+    /// A machine about to run the program, sharing its decoded instructions.
+    pub fn vm(&self) -> VM<W> {
+        let mut vm = VM::default();
+        vm.mem.reset(&self.mem);
+        vm.cache = self.cache.clone();
+        vm
+    }
+}
+
+impl<W: Word> VM<W> {
+    /// Puts a machine created by `image.vm()` back into the state it started
+    /// in. Profiling, recording, undo, the budget and the deadline are
+    /// switched off; custom opcodes, the memory limit and whether the decode
+    /// cache is used are kept.
+    pub(crate) fn reset(&mut self, image: &ProgramImage<W>) {
+        self.ip = 0;
+        self.rp = 0;
+        self.pending.clear();
+        self.mem.reset(&image.mem);
+        if self.cache.is_enabled() {
+            self.cache = image.cache.clone();
+        } else {
+            self.cache.clear();
+        }
+        self.profile = None;
+        self.transcript = None;
+        self.undo = None;
+        self.executed = 0;
+        self.budget = None;
+        self.deadline = None;
+    }
+}
+
+impl<W: Word> Pool<W> {
+    pub fn new(image: ProgramImage<W>) -> Self {
+        Pool { image, idle: Mutex::default() }
+    }
+
+    pub fn image(&self) -> &ProgramImage<W> {
+        &self.image
+    }
+
+    /// A machine about to run the program.
+    pub fn get(&self) -> Pooled<'_, W> {
+        let vm = self.idle.lock().unwrap().pop();
+        let vm = vm.unwrap_or_else(|| self.image.vm());
+        Pooled { pool: self, vm: Some(vm) }
+    }
+
+    /// Runs the program once for every input tuple, in parallel, and
+    /// collects what each run outputs. A run ends when the program halts or
+    /// has read all of its inputs.
+    pub fn map<T>(&self, inputs: &[T]) -> Result<Vec<Vec<W>>, Error>
+    where
+        T: AsRef<[W]> + Sync,
+        W: Sync,
+    {
+        inputs
+            .par_iter()
+            .map_init(
+                || self.get(),
+                |vm, input| {
+                    let mut output = Vec::new();
+                    let input = io::Iter(input.as_ref().iter().cloned());
+                    let exit = vm.run_with(input, &mut output);
+                    vm.reset(&self.image);
+                    exit.into_result()?;
+                    Ok(output)
+                },
+            )
+            .collect()
+    }
+}
+
+impl<W: Word> Deref for Pooled<'_, W> {
+    type Target = VM<W>;
+
+    fn deref(&self) -> &VM<W> {
+        self.vm.as_ref().unwrap()
+    }
+}
+
+impl<W: Word> DerefMut for Pooled<'_, W> {
+    fn deref_mut(&mut self) -> &mut VM<W> {
+        self.vm.as_mut().unwrap()
+    }
+}
+
+impl<W: Word> Drop for Pooled<'_, W> {
+    fn drop(&mut self) {
+        let mut vm = self.vm.take().unwrap();
+        vm.reset(&self.pool.image);
+        self.pool.idle.lock().unwrap().push(vm);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Event, PAGE_SIZE};
+
+    #[test_log::new]
+    fn reuse() -> aoc::Result<()> {
+        // Outputs 1 if the input equals 8, and 0 otherwise.
+        let image = ProgramImage::parse("3,9,8,9,10,9,4,9,99,-1,8")?;
+        let pool = Pool::new(image);
+        {
+            let mut vm = pool.get();
+            vm.poke(3 * PAGE_SIZE, 1)?;
+            vm.poke(1 << 40, 1)?;
+            vm.provide_input(8);
+            assert_eq!(vm.resume()?, Event::Output(1));
+        }
+        let vm = pool.get();
+        assert_eq!(vm.mem(), pool.image().mem());
+        assert_eq!(vm.peek(1 << 40), 0);
+        assert_eq!(vm.memory_stats().pages, 0);
+        assert_eq!((vm.ip(), vm.instructions_executed()), (0, 0));
+        drop(vm);
+        assert_eq!(pool.idle.lock().unwrap().len(), 1);
+
+        // Restoring a snapshot replaces all of memory, not just some pages.
+        {
+            let mut vm = pool.get();
+            vm.poke(10, 123)?;
+            let snapshot = vm.snapshot();
+            vm.restore(&snapshot)?;
+        }
+        assert_eq!(pool.get().peek(10), 8);
+
+        let inputs = (0..100).map(|x| vec![x % 10]).collect::<Vec<_>>();
+        let outputs = pool.map(&inputs)?;
+        for (input, output) in inputs.iter().zip(&outputs) {
+            assert_eq!(output, &[(input[0] == 8) as Value]);
+        }
+        let broken: Pool = Pool::new(ProgramImage::new(&[3, 3, 4, 98]));
+        assert!(broken.map(&[[1]]).is_err());
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn reset_keeps_cache_disabled() -> aoc::Result<()> {
+        let pool: Pool =
+            Pool::new(ProgramImage::parse("3,9,8,9,10,9,4,9,99,-1,8")?);
+        {
+            let mut vm = pool.get();
+            vm.set_decode_cache(false);
+            vm.provide_input(8);
+            assert_eq!(vm.resume()?, Event::Output(1));
+        }
+        let mut vm = pool.get();
+        assert!(!vm.cache.is_enabled());
+        vm.provide_input(7);
+        assert_eq!(vm.resume()?, Event::Output(0));
+        assert!(!vm.cache.is_enabled());
+        Ok(())
+    }
+}
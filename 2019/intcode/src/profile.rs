@@ -0,0 +1,159 @@
+use crate::{decode_at, Opcode, Value};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Execution counts collected by a `VM` with profiling enabled.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+    pub total: u64,
+    pub per_address: BTreeMap<usize, u64>,
+    pub per_opcode: BTreeMap<Opcode, u64>,
+    pub branches: BTreeMap<usize, Branch>,
+}
+
+/// Outcomes of the `Jit`/`Jif` instruction at one address.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+    pub targets: BTreeMap<usize, u64>,
+}
+
+/// A backward jump from `end` to `start`, taken `iterations` times.
+/// `executed` counts all instructions executed between the two addresses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Loop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    pub executed: u64,
+}
+
+impl Profile {
+    pub(crate) fn record(
+        &mut self,
+        address: usize,
+        opcode: Opcode,
+        branch: Option<(bool, usize)>,
+    ) {
+        self.total += 1;
+        *self.per_address.entry(address).or_default() += 1;
+        *self.per_opcode.entry(opcode).or_default() += 1;
+        if let Some((taken, target)) = branch {
+            let b = self.branches.entry(address).or_default();
+            if taken {
+                b.taken += 1;
+                *b.targets.entry(target).or_default() += 1;
+            } else {
+                b.not_taken += 1;
+            }
+        }
+    }
+
+    /// Backward jumps ordered by the number of instructions they enclose.
+    pub fn hot_loops(&self) -> Vec<Loop> {
+        let mut loops = self
+            .branches
+            .iter()
+            .flat_map(|(&end, b)| {
+                b.targets.iter().filter(move |&(&t, _)| t <= end).map(
+                    move |(&start, &iterations)| {
+                        let executed = self
+                            .per_address
+                            .range(start..=end)
+                            .map(|(_, n)| n)
+                            .sum();
+                        Loop { start, end, iterations, executed }
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|l| (std::cmp::Reverse(l.executed), l.start));
+        loops
+    }
+
+    /// Renders a human readable summary, disassembling the `top` hottest
+    /// addresses from `mem`.
+    pub fn report(&self, mem: &[Value], top: usize) -> String {
+        let percent = |n: u64| 100.0 * n as f64 / self.total.max(1) as f64;
+        let mut out = String::new();
+        let _ = writeln!(out, "instructions executed: {}", self.total);
+
+        let _ = writeln!(out, "\nopcodes:");
+        let mut opcodes = self.per_opcode.iter().collect::<Vec<_>>();
+        opcodes.sort_by_key(|&(_, &n)| std::cmp::Reverse(n));
+        for (opcode, &n) in opcodes {
+            let name = opcode.mnemonic();
+            let _ =
+                writeln!(out, "  {:<4}{:>12} {:>6.2}%", name, n, percent(n));
+        }
+
+        let _ = writeln!(out, "\nhot addresses:");
+        let mut addresses = self.per_address.iter().collect::<Vec<_>>();
+        addresses.sort_by_key(|&(&a, &n)| (std::cmp::Reverse(n), a));
+        for (&address, &n) in addresses.into_iter().take(top) {
+            let _ = write!(out, "  {:>12} {:>6.2}%", n, percent(n));
+            if address < mem.len() {
+                let _ = write!(out, "  {}", decode_at(mem, address));
+            }
+            let _ = writeln!(out);
+        }
+
+        let _ = writeln!(out, "\nbranches:");
+        for (address, b) in &self.branches {
+            let _ = writeln!(
+                out,
+                "  {:>6}: taken {:>10}  not taken {:>10}",
+                address, b.taken, b.not_taken
+            );
+        }
+
+        let _ = writeln!(out, "\nhot loops:");
+        for l in self.hot_loops().into_iter().take(top) {
+            let _ = writeln!(
+                out,
+                "  {:>6}..={:<6} iterations {:>10}  executed {:>12} {:>6.2}%",
+                l.start,
+                l.end,
+                l.iterations,
+                l.executed,
+                percent(l.executed)
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assemble, Event, Loop, Opcode, VM};
+
+    #[test_log::new]
+    fn countdown() -> aoc::Result<()> {
+        let program = assemble(
+            r#"
+            loop:   add  [n], #-1, [n]
+                    jt   [n], #loop
+                    out  [n]
+                    hlt
+            n:      db   3
+            "#,
+        )?;
+
+        let mut vm = VM::with_mem(&program);
+        vm.enable_profiling();
+        assert_eq!(vm.resume()?, Event::Output(0));
+        assert_eq!(vm.resume()?, Event::Halted);
+
+        let profile = vm.profile().unwrap();
+        assert_eq!(profile.total, 7);
+        assert_eq!(profile.per_address[&0], 3);
+        assert_eq!(profile.per_opcode[&Opcode::Jit], 3);
+        assert_eq!(profile.branches[&4].taken, 2);
+        assert_eq!(profile.branches[&4].not_taken, 1);
+        assert_eq!(
+            profile.hot_loops(),
+            [Loop { start: 0, end: 4, iterations: 2, executed: 6 }]
+        );
+        Ok(())
+    }
+}
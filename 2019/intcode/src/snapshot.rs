@@ -0,0 +1,120 @@
+use crate::Value;
+use std::{fmt, str::FromStr};
+
+/// The complete execution state of a `VM`, without its IO channels.
+///
+/// A snapshot serializes to a small line based text format:
+///
+/// ```text
+/// ip 2
+/// rp 0
+/// input 8
+/// mem 3,9,8,9,10,9,4,9,99,-1,8
+/// sparse 1000000=5
+/// ```
+///
+/// The `sparse` line lists non-zero cells outside the dense region and may
+/// be omitted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    pub ip: usize,
+    pub rp: Value,
+    pub pending: Vec<Value>,
+    pub mem: Vec<Value>,
+    pub sparse: Vec<(usize, Value)>,
+}
+
+fn write_values(f: &mut fmt::Formatter<'_>, xs: &[Value]) -> fmt::Result {
+    for (i, x) in xs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", x)?;
+    }
+    Ok(())
+}
+
+fn parse_values(s: &str) -> aoc::Result<Vec<Value>> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(',').map(|x| Ok(x.trim().parse()?)).collect()
+}
+
+fn parse_sparse(s: &str) -> aoc::Result<Vec<(usize, Value)>> {
+    let mut cells = Vec::new();
+    for cell in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let i = cell
+            .find('=')
+            .ok_or_else(|| aoc::format_err!("Invalid sparse cell: {}", cell))?;
+        cells.push((cell[..i].parse()?, cell[i + 1..].parse()?));
+    }
+    Ok(cells)
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ip {}", self.ip)?;
+        writeln!(f, "rp {}", self.rp)?;
+        write!(f, "input ")?;
+        write_values(f, &self.pending)?;
+        write!(f, "\nmem ")?;
+        write_values(f, &self.mem)?;
+        writeln!(f)?;
+        if !self.sparse.is_empty() {
+            write!(f, "sparse ")?;
+            for (i, (address, x)) in self.sparse.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}={}", address, x)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut snapshot = Snapshot::default();
+        let mut seen = [false; 4];
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            let i = match key {
+                "ip" => {
+                    snapshot.ip = value.parse()?;
+                    0
+                },
+                "rp" => {
+                    snapshot.rp = value.parse()?;
+                    1
+                },
+                "input" => {
+                    snapshot.pending = parse_values(value)?;
+                    2
+                },
+                "mem" => {
+                    snapshot.mem = parse_values(value)?;
+                    3
+                },
+                "sparse" => {
+                    snapshot.sparse = parse_sparse(value)?;
+                    continue;
+                },
+                k => return aoc::err!("Unknown snapshot field: {}", k),
+            };
+            seen[i] = true;
+        }
+
+        if seen.iter().any(|&s| !s) {
+            return aoc::err!("Incomplete snapshot");
+        }
+        Ok(snapshot)
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{Event, Exit, ExitReason, Word, VM};
+use futures::{channel::mpsc, Future, Sink, SinkExt, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// How many instructions run before handing control back to the executor.
+const YIELD_INTERVAL: u64 = 4096;
+
+/// Completes the second time it is polled, giving other tasks a turn.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<W: Word> VM<W> {
+    /// Like `run_with`, but waits for input from a stream and sends output
+    /// into a sink, so the machine can share an executor with other tasks.
+    /// Stops when the program halts, `input` ends or `output` is closed.
+    pub async fn run_async<I, O>(&mut self, mut input: I, mut output: O) -> Exit
+    where
+        I: Stream<Item = W> + Unpin,
+        O: Sink<W> + Unpin,
+    {
+        let mut steps = 0u64;
+        let reason = loop {
+            let event = match self.step() {
+                Ok(Some(event)) => event,
+                Ok(None) => {
+                    steps += 1;
+                    if steps.is_multiple_of(YIELD_INTERVAL) {
+                        YieldNow(false).await;
+                    }
+                    continue;
+                },
+                Err(e) => break e.into(),
+            };
+            match event {
+                Event::NeedsInput => match input.next().await {
+                    Some(x) => self.provide_input(x),
+                    None => break ExitReason::InputClosed,
+                },
+                Event::Output(x) =>
+                    if output.send(x).await.is_err() {
+                        break ExitReason::OutputClosed;
+                    },
+                Event::Halted => break ExitReason::Halted,
+            }
+        };
+
+        let _ = output.close().await;
+        self.exit(reason)
+    }
+
+    /// Turns the machine into a task connected through channels that hold
+    /// up to `buffer` values, so a machine that runs ahead of its consumer
+    /// waits for it. Unlike `spawn`, the task reports how the run ended.
+    pub fn into_task(
+        mut self,
+        buffer: usize,
+    ) -> (mpsc::Sender<W>, mpsc::Receiver<W>, impl Future<Output = Exit>) {
+        let (tx, input) = mpsc::channel(buffer);
+        let (output, rx) = mpsc::channel(buffer);
+        let task = async move { self.run_async(input, output).await };
+        (tx, rx, task)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, ExitReason, Value, VM};
+    use futures::{
+        channel::mpsc,
+        executor::block_on,
+        future::{join, join_all},
+        SinkExt, StreamExt,
+    };
+
+    #[test_log::new]
+    fn feedback_loop() -> aoc::Result<()> {
+        let program = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,\
+                       1001,28,-1,28,1005,28,6,99,0,0,5";
+        let template = VM::with_program(program)?;
+        let mut vms = vec![template; 5];
+
+        // Amplifier i reads from channel i and writes to channel i + 1.
+        let (txs, mut rxs): (Vec<_>, Vec<_>) =
+            (0..5).map(|_| mpsc::channel::<Value>(2)).unzip();
+        for (tx, phase) in txs.iter().zip(&[9, 8, 7, 6, 5]) {
+            tx.clone().try_send(*phase)?;
+        }
+        txs[0].clone().try_send(0)?;
+
+        let amplifiers =
+            vms.iter_mut().zip(&mut rxs).enumerate().map(|(i, (vm, rx))| {
+                vm.run_async(rx, txs[(i + 1) % 5].clone())
+            });
+        let results = block_on(join_all(amplifiers));
+        assert!(results.iter().all(|e| e.reason == ExitReason::Halted));
+        drop(txs);
+        assert_eq!(block_on(rxs[0].next()), Some(139_629_729));
+        Ok(())
+    }
+
+    #[test_log::new]
+    fn task_reports_errors() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let vm = VM::with_mem(&[3, 5, 4, 5, 98, 0]);
+        let (mut tx, rx, task) = vm.into_task(1);
+        assert_send(&task);
+        let outputs = async {
+            tx.send(7).await.unwrap();
+            rx.collect::<Vec<_>>().await
+        };
+        let (outputs, exit) = block_on(join(outputs, task));
+        assert_eq!(outputs, [7]);
+        let fault = Error::UnknownOpcode { ip: 4, value: 98 };
+        assert_eq!(exit.reason, ExitReason::Faulted(fault));
+    }
+}
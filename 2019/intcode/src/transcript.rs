@@ -0,0 +1,158 @@
+use crate::{Event, Value, Word, VM};
+use std::{fmt, str::FromStr};
+
+/// A value that went into or came out of a machine.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Entry<W = Value> {
+    Input(W),
+    Output(W),
+}
+
+/// Every value a machine consumed and produced, in the order it did so.
+///
+/// A transcript serializes to one line per run of inputs or outputs:
+///
+/// ```text
+/// out 1,2,3
+/// in 0
+/// out 1,3,4
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transcript<W = Value> {
+    pub entries: Vec<Entry<W>>,
+}
+
+impl<W> Transcript<W> {
+    pub fn inputs(&self) -> impl Iterator<Item = &W> {
+        self.entries.iter().filter_map(|e| match e {
+            Entry::Input(x) => Some(x),
+            Entry::Output(_) => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &W> {
+        self.entries.iter().filter_map(|e| match e {
+            Entry::Output(x) => Some(x),
+            Entry::Input(_) => None,
+        })
+    }
+}
+
+impl<W: Word> Transcript<W> {
+    /// Runs `vm` until it halts, feeding it the recorded inputs and checking
+    /// that it produces exactly the recorded outputs.
+    pub fn replay(&self, vm: &mut VM<W>) -> aoc::Result<()> {
+        let mut entries = self.entries.iter().enumerate();
+        loop {
+            let event = vm.resume()?;
+            let (i, entry) = match entries.next() {
+                Some(entry) => entry,
+                None if event == Event::Halted => return Ok(()),
+                None => return aoc::err!("Unexpected {:?} at the end", event),
+            };
+            match (entry, event) {
+                (Entry::Input(x), Event::NeedsInput) =>
+                    vm.provide_input(x.clone()),
+                (Entry::Output(x), Event::Output(y)) if *x == y => {},
+                (entry, event) =>
+                    return aoc::err!(
+                        "Expected {:?} at entry {}, got {:?}",
+                        entry,
+                        i,
+                        event
+                    ),
+            }
+        }
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for Transcript<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut last = None;
+        for entry in &self.entries {
+            let (key, x) = match entry {
+                Entry::Input(x) => ("in", x),
+                Entry::Output(x) => ("out", x),
+            };
+            match last {
+                Some(k) if k == key => write!(f, ",{}", x)?,
+                Some(_) => write!(f, "\n{} {}", key, x)?,
+                None => write!(f, "{} {}", key, x)?,
+            }
+            last = Some(key);
+        }
+        if last.is_some() {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: FromStr> FromStr for Transcript<W>
+where
+    W::Err: std::error::Error + 'static,
+{
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, values) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            let entry = match key {
+                "in" => Entry::Input,
+                "out" => Entry::Output,
+                k => return aoc::err!("Unknown transcript entry: {}", k),
+            };
+            for x in values.split(',') {
+                entries.push(entry(x.trim().parse()?));
+            }
+        }
+        Ok(Transcript { entries })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assemble, io};
+
+    #[test_log::new]
+    fn record_and_replay() -> aoc::Result<()> {
+        // Echoes its input until it reads a zero, then outputs 42.
+        let program = &assemble(
+            r#"
+            loop:   in   [x]
+                    jf   [x], #done
+                    out  [x]
+                    jt   #1, #loop
+            done:   out  #42
+                    hlt
+            x:      db   0
+            "#,
+        )?;
+
+        let mut vm = VM::with_mem(program);
+        vm.enable_recording();
+        let mut output = Vec::new();
+        let input = io::Iter(vec![7, 5, 0].into_iter());
+        vm.run_with(input, &mut output).into_result()?;
+        assert_eq!(output, [7, 5, 42]);
+
+        let transcript = vm.take_transcript().unwrap();
+        let text = transcript.to_string();
+        assert_eq!(text, "in 7\nout 7\nin 5\nout 5\nin 0\nout 42\n");
+        let parsed = text.parse::<Transcript>()?;
+        assert_eq!(parsed, transcript);
+        assert_eq!(parsed.inputs().copied().collect::<Vec<_>>(), [7, 5, 0]);
+
+        parsed.replay(&mut VM::with_mem(program))?;
+        let tampered = text.replace("out 5", "out 6").parse::<Transcript>()?;
+        assert!(tampered.replay(&mut VM::with_mem(program)).is_err());
+        let short = "in 7\nout 7\n".parse::<Transcript>()?;
+        assert!(short.replay(&mut VM::with_mem(program)).is_err());
+        Ok(())
+    }
+}
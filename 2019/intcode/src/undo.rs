@@ -0,0 +1,192 @@
+use crate::{Value, Word, VM};
+use std::collections::VecDeque;
+
+#[derive(Clone, Debug)]
+enum Change<W> {
+    Write {
+        address: usize,
+        old: W,
+    },
+    /// Closes the writes of one instruction, holding the registers from
+    /// before it ran and the input it consumed.
+    Step {
+        ip: usize,
+        rp: Value,
+        input: Option<W>,
+    },
+}
+
+/// Everything needed to undo the last `capacity` instructions.
+#[derive(Clone, Debug)]
+pub(crate) struct UndoLog<W> {
+    changes: VecDeque<Change<W>>,
+    steps: usize,
+    capacity: usize,
+}
+
+impl<W> UndoLog<W> {
+    pub fn new(capacity: usize) -> Self {
+        UndoLog { changes: VecDeque::new(), steps: 0, capacity }
+    }
+
+    pub fn write(&mut self, address: usize, old: W) {
+        self.changes.push_back(Change::Write { address, old });
+    }
+
+    pub fn commit(&mut self, ip: usize, rp: Value, input: Option<W>) {
+        self.changes.push_back(Change::Step { ip, rp, input });
+        self.steps += 1;
+        while self.steps > self.capacity {
+            if let Some(Change::Step { .. }) = self.changes.pop_front() {
+                self.steps -= 1;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.steps = 0;
+    }
+}
+
+impl<W: Word> VM<W> {
+    /// Starts recording how to undo each instruction, keeping the last
+    /// `capacity` of them. Undoing does not take back output, nor what a
+    /// transcript or profile recorded.
+    pub fn enable_undo(&mut self, capacity: usize) {
+        self.undo = Some(UndoLog::new(capacity));
+    }
+
+    pub fn disable_undo(&mut self) {
+        self.undo = None;
+    }
+
+    /// Forgets all history, for when memory is replaced as a whole.
+    pub(crate) fn clear_undo(&mut self) {
+        if let Some(undo) = &mut self.undo {
+            undo.clear();
+        }
+    }
+
+    /// Number of instructions that can currently be undone.
+    pub fn undo_depth(&self) -> usize {
+        self.undo.as_ref().map_or(0, |undo| undo.steps)
+    }
+
+    /// Undoes up to `n` instructions, returning how many were undone.
+    /// Writes made with `poke` are not undone.
+    pub fn step_back(&mut self, n: u64) -> u64 {
+        let mut undone = 0;
+        while undone < n && self.undo_one() {
+            undone += 1;
+        }
+        undone
+    }
+
+    /// Undoes instructions until `ip` is at an address `stop` accepts, or
+    /// returns `false` if the log runs out first. At least one instruction
+    /// is undone, so repeated calls keep going back to earlier stops.
+    pub fn rewind_until<F>(&mut self, mut stop: F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        while self.undo_one() {
+            if stop(self.ip) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn undo_one(&mut self) -> bool {
+        let mut undo = match self.undo.take() {
+            Some(undo) if undo.steps > 0 => undo,
+            undo => {
+                self.undo = undo;
+                return false;
+            },
+        };
+        // Writes after the newest step come from an instruction that failed
+        // or has to be retried, and are undone along with it.
+        let mut seen_step = false;
+        while let Some(change) = undo.changes.pop_back() {
+            match change {
+                Change::Write { address, old } => {
+                    // Old values fit in memory that is already allocated.
+                    let _ = self.assign(self.ip, address, old);
+                },
+                Change::Step { .. } if seen_step => {
+                    undo.changes.push_back(change);
+                    break;
+                },
+                Change::Step { ip, rp, input } => {
+                    self.ip = ip;
+                    self.rp = rp;
+                    if let Some(x) = input {
+                        self.pending.push_front(x);
+                    }
+                    seen_step = true;
+                },
+            }
+        }
+        undo.steps -= 1;
+        self.executed -= 1;
+        self.undo = Some(undo);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Event, VM};
+
+    #[test_log::new]
+    fn step_back() -> aoc::Result<()> {
+        let state = |vm: &VM| {
+            let mem = (0..128).map(|a| vm.peek(a)).collect::<Vec<_>>();
+            (vm.ip(), vm.rp(), vm.pending_input().clone(), mem)
+        };
+        // Reads a counter, then outputs memory cells until it reaches 16.
+        let program = "3,100,109,1,204,-1,1001,100,1,100,1008,100,16,101,\
+                       1006,101,2,99";
+        let mut vm = VM::with_program(program)?;
+        vm.enable_undo(usize::MAX);
+        assert_eq!(vm.step_back(1), 0);
+        vm.provide_input(3);
+        let mut states = Vec::new();
+        loop {
+            states.push(state(&vm));
+            if vm.step()? == Some(Event::Halted) {
+                break;
+            }
+        }
+        assert_eq!(state(&vm), states.pop().unwrap());
+        let executed = vm.instructions_executed() as usize;
+        assert_eq!((vm.undo_depth(), states.len()), (executed, executed));
+
+        // Back to the output instruction of the previous iterations.
+        assert!(vm.rewind_until(|ip| ip == 4));
+        assert_eq!(vm.peek(100), 15);
+        assert!(vm.rewind_until(|ip| ip == 4));
+        assert_eq!(vm.peek(100), 14);
+        assert_eq!(vm.resume()?, Event::Output(100));
+        while vm.resume()? != Event::Halted {}
+
+        while let Some(expected) = states.pop() {
+            assert_eq!(vm.step_back(1), 1);
+            assert_eq!(state(&vm), expected);
+        }
+        assert_eq!(vm.instructions_executed(), 0);
+        assert_eq!(vm.step_back(1), 0);
+
+        while vm.resume()? != Event::Halted {}
+        assert!(!vm.rewind_until(|ip| ip == 1));
+        assert_eq!(vm.pending_input(), &[3]);
+
+        // Only the most recent instructions are kept.
+        vm.enable_undo(5);
+        while vm.resume()? != Event::Halted {}
+        assert_eq!(vm.step_back(10), 5);
+        Ok(())
+    }
+}
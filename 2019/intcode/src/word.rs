@@ -0,0 +1,86 @@
+use crate::Value;
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// A memory cell of a `VM`. Addresses, opcodes and the relative base are
+/// always plain `Value`s, so a word only needs to convert to one when it is
+/// used as such.
+pub trait Word:
+    Clone + fmt::Debug + fmt::Display + Default + Ord + FromStr + Send + 'static
+{
+    fn from_value(x: Value) -> Self;
+
+    /// `None` if the word does not fit in a `Value`.
+    fn to_value(&self) -> Option<Value>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Word for i64 {
+    #[inline]
+    fn from_value(x: Value) -> Self {
+        x
+    }
+
+    #[inline]
+    fn to_value(&self) -> Option<Value> {
+        Some(*self)
+    }
+
+    #[inline]
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    #[inline]
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    #[inline]
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+impl Word for i128 {
+    fn from_value(x: Value) -> Self {
+        x.into()
+    }
+
+    fn to_value(&self) -> Option<Value> {
+        Value::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn from_value(x: Value) -> Self {
+        x.into()
+    }
+
+    fn to_value(&self) -> Option<Value> {
+        Value::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
@@ -0,0 +1,240 @@
+//! Checks that the standalone interpreters from days 2 and 5 and the shared
+//! VM agree, both on the published examples and on random programs.
+
+use intcode::{io, Value, VM};
+use std::convert::TryFrom;
+
+/// Final memory and outputs.
+type Outcome = (Vec<Value>, Vec<Value>);
+
+fn run_day02(program: &[Value]) -> Vec<Value> {
+    let mem = program.iter().map(|&x| usize::try_from(x).unwrap()).collect();
+    day02::run_intcode(mem).into_iter().map(|x| x as Value).collect()
+}
+
+fn run_day05(program: &[Value], inputs: &[Value]) -> Outcome {
+    let narrow = |xs: &[Value]| {
+        xs.iter().map(|&x| i32::try_from(x).unwrap()).collect::<Vec<_>>()
+    };
+    let mut mem = narrow(program);
+    let outputs = day05::execute(&mut mem, &narrow(inputs));
+    let widen = |xs: Vec<i32>| xs.into_iter().map(Value::from).collect();
+    (widen(mem), widen(outputs))
+}
+
+fn run_vm(program: &[Value], inputs: &[Value]) -> Outcome {
+    let mut vm = VM::with_mem(program);
+    let mut outputs = Vec::new();
+    let input = io::Iter(inputs.iter().copied());
+    vm.run_with(input, &mut outputs).into_result().unwrap();
+    (vm.mem().to_vec(), outputs)
+}
+
+fn parse(program: &str) -> Vec<Value> {
+    program.split(',').map(|x| x.parse().unwrap()).collect()
+}
+
+#[test]
+fn day02_examples() {
+    let examples = [
+        ("1,9,10,3,2,3,11,0,99,30,40,50", "3500,9,10,70,2,3,11,0,99,30,40,50"),
+        ("1,0,0,0,99", "2,0,0,0,99"),
+        ("2,3,0,3,99", "2,3,0,6,99"),
+        ("2,4,4,5,99,0", "2,4,4,5,99,9801"),
+        ("1,1,1,4,99,5,6,0,99", "30,1,1,4,2,5,6,0,99"),
+    ];
+    for &(program, expected) in &examples {
+        let (program, expected) = (parse(program), parse(expected));
+        assert_eq!(run_day02(&program), expected, "day 2 on {:?}", program);
+        let day05 = run_day05(&program, &[]).0;
+        assert_eq!(day05, expected, "day 5 on {:?}", program);
+        assert_eq!(run_vm(&program, &[]).0, expected, "VM on {:?}", program);
+    }
+}
+
+#[test]
+fn day05_examples() {
+    let larger = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,\
+                  36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,\
+                  1101,1000,1,20,4,20,1105,1,46,98,99";
+    let examples: &[(&str, &[Value], &[Value])] = &[
+        ("3,0,4,0,99", &[42], &[42]),
+        ("1002,4,3,4,33", &[], &[]),
+        ("1101,100,-1,4,0", &[], &[]),
+        ("3,9,8,9,10,9,4,9,99,-1,8", &[8], &[1]),
+        ("3,9,8,9,10,9,4,9,99,-1,8", &[7], &[0]),
+        ("3,9,7,9,10,9,4,9,99,-1,8", &[7], &[1]),
+        ("3,9,7,9,10,9,4,9,99,-1,8", &[8], &[0]),
+        ("3,3,1108,-1,8,3,4,3,99", &[8], &[1]),
+        ("3,3,1108,-1,8,3,4,3,99", &[9], &[0]),
+        ("3,3,1107,-1,8,3,4,3,99", &[5], &[1]),
+        ("3,3,1107,-1,8,3,4,3,99", &[8], &[0]),
+        ("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", &[0], &[0]),
+        ("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", &[3], &[1]),
+        ("3,3,1105,-1,9,1101,0,0,12,4,12,99,1", &[0], &[0]),
+        ("3,3,1105,-1,9,1101,0,0,12,4,12,99,1", &[3], &[1]),
+        (larger, &[7], &[999]),
+        (larger, &[8], &[1000]),
+        (larger, &[9], &[1001]),
+    ];
+    for &(program, inputs, expected) in examples {
+        let program = parse(program);
+        let day05 = run_day05(&program, inputs);
+        assert_eq!(day05.1, expected, "day 5 on {:?}", program);
+        assert_eq!(run_vm(&program, inputs), day05, "VM on {:?}", program);
+
+        let last = expected.last().copied().unwrap_or(0);
+        let mut mem = program.iter().map(|&x| x as i32).collect::<Vec<_>>();
+        let inputs = inputs.iter().map(|&x| x as i32).collect::<Vec<_>>();
+        assert_eq!(Value::from(day05::run_intcode(&mut mem, &inputs)), last);
+    }
+}
+
+#[test]
+fn day09_examples() {
+    let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+    let examples: &[(&str, &[Value])] = &[
+        (quine, &parse(quine)),
+        ("1102,34915192,34915192,7,4,7,99,0", &[1_219_070_632_396_864]),
+        ("104,1125899906842624,99", &[1_125_899_906_842_624]),
+    ];
+    for &(program, expected) in examples {
+        assert_eq!(run_vm(&parse(program), &[]).1, expected);
+    }
+}
+
+/// xorshift64, so that failures can be reproduced without extra
+/// dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn between(&mut self, lo: Value, hi: Value) -> Value {
+        lo + self.below((hi - lo + 1) as usize) as Value
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dialect {
+    /// Add and multiply in positional mode only.
+    Day02,
+    /// Everything up to day 5: IO, comparisons, jumps and immediate mode.
+    Day05,
+}
+
+const VARS: usize = 8;
+const CONSTS: usize = 4;
+
+/// Generates a program that is guaranteed to halt and stay within the range
+/// of every interpreter: jumps only go forward, writes only hit the variables
+/// after the code, and every multiplication has a small constant operand.
+fn generate(rng: &mut Rng, dialect: Dialect) -> (Vec<Value>, Vec<Value>) {
+    let opcodes: &[Value] = match dialect {
+        Dialect::Day02 => &[1, 2],
+        Dialect::Day05 => &[1, 2, 3, 4, 5, 6, 7, 8],
+    };
+    let ops = (0..=rng.below(12))
+        .map(|_| opcodes[rng.below(opcodes.len())])
+        .collect::<Vec<_>>();
+    let mut starts = vec![0];
+    for &op in &ops {
+        let size = match op {
+            3 | 4 => 2,
+            5 | 6 => 3,
+            _ => 4,
+        };
+        starts.push(starts.last().unwrap() + size);
+    }
+    let vars = *starts.last().unwrap() + 1;
+    let consts = vars + VARS;
+    let len = consts + CONSTS;
+
+    let small = match dialect {
+        Dialect::Day02 => 0,
+        Dialect::Day05 => -3,
+    };
+    let mut program = Vec::new();
+    let mut inputs = Vec::new();
+    for (i, &op) in ops.iter().enumerate() {
+        let mut modes = 0;
+        let mut params = Vec::new();
+        // (mode, value) of a parameter that is read.
+        let read = |rng: &mut Rng, konst: bool| {
+            if dialect == Dialect::Day05 && rng.below(2) == 0 {
+                let x = if konst { 3 } else { 9 };
+                (1, rng.between(-x, x))
+            } else if konst {
+                (0, (consts + rng.below(CONSTS)) as Value)
+            } else {
+                (0, rng.below(len) as Value)
+            }
+        };
+        let var = |rng: &mut Rng| (vars + rng.below(VARS)) as Value;
+        match op {
+            1 | 7 | 8 => {
+                let (a, b) = (read(rng, false), read(rng, false));
+                modes = a.0 + 10 * b.0;
+                params.extend(&[a.1, b.1, var(rng)]);
+            },
+            2 => {
+                let (a, b) = (read(rng, false), read(rng, true));
+                modes = a.0 + 10 * b.0;
+                params.extend(&[a.1, b.1, var(rng)]);
+            },
+            3 => {
+                inputs.push(rng.between(-9, 9));
+                params.push(var(rng));
+            },
+            4 => {
+                let a = read(rng, false);
+                modes = a.0;
+                params.push(a.1);
+            },
+            _ => {
+                let cond = read(rng, false);
+                let target = starts[i + 1 + rng.below(ops.len() - i)];
+                modes = cond.0 + 10;
+                params.extend(&[cond.1, target as Value]);
+            },
+        }
+        program.push(op + 100 * modes);
+        program.extend(params);
+    }
+    program.push(99);
+    program.extend((0..VARS).map(|_| rng.between(small, 9)));
+    program.extend((0..CONSTS).map(|_| rng.between(small, 3)));
+    (program, inputs)
+}
+
+#[test]
+fn random_day02_programs() {
+    let mut rng = Rng(0x2019_0002);
+    for _ in 0..1000 {
+        let (program, _) = generate(&mut rng, Dialect::Day02);
+        let day02 = run_day02(&program);
+        assert_eq!(run_day05(&program, &[]).0, day02, "day 5 on {:?}", program);
+        assert_eq!(run_vm(&program, &[]).0, day02, "VM on {:?}", program);
+    }
+}
+
+#[test]
+fn random_day05_programs() {
+    let mut rng = Rng(0x2019_0005);
+    for _ in 0..1000 {
+        let (program, inputs) = generate(&mut rng, Dialect::Day05);
+        let day05 = run_day05(&program, &inputs);
+        assert_eq!(
+            run_vm(&program, &inputs),
+            day05,
+            "VM on {:?} with inputs {:?}",
+            program,
+            inputs
+        );
+    }
+}